@@ -0,0 +1,252 @@
+use crate::{
+    MidiSink, PianoffError, check_range, create_cc_message, display_channel,
+    interpret_local_control_value,
+};
+
+/// Channel Mode messages (Control Change numbers 120-127)
+/// Each one only accepts the values allowed by the MIDI 1.0 specification
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChannelMode {
+    /// CC #120, value 0: silence everything immediately, including releases
+    AllSoundOff,
+    /// CC #121, value 0: return all controllers to their default state
+    ResetAllControllers,
+    /// CC #122: 0 = off, 127 = on
+    LocalControl(u8),
+    /// CC #123, value 0: release every sounding note
+    AllNotesOff,
+    /// CC #124, value 0
+    OmniOff,
+    /// CC #125, value 0
+    OmniOn,
+    /// CC #126: number of mono channels (1-16), or 0 for as many as voices
+    MonoOn(u8),
+    /// CC #127, value 0
+    PolyOn,
+}
+
+impl ChannelMode {
+    /// Returns the controller number of this Channel Mode message
+    pub fn controller(&self) -> u8 {
+        match self {
+            ChannelMode::AllSoundOff => 120,
+            ChannelMode::ResetAllControllers => 121,
+            ChannelMode::LocalControl(_) => 122,
+            ChannelMode::AllNotesOff => 123,
+            ChannelMode::OmniOff => 124,
+            ChannelMode::OmniOn => 125,
+            ChannelMode::MonoOn(_) => 126,
+            ChannelMode::PolyOn => 127,
+        }
+    }
+
+    /// Returns the data value sent with this Channel Mode message
+    pub fn value(&self) -> u8 {
+        match self {
+            ChannelMode::LocalControl(value) | ChannelMode::MonoOn(value) => *value,
+            _ => 0,
+        }
+    }
+
+    /// Builds a Channel Mode message from a controller number and value
+    /// Fails when the controller is not 120-127 or the value is not allowed for it
+    pub fn from_cc(controller: u8, value: u8) -> Result<Self, PianoffError> {
+        let mode = match controller {
+            120 => ChannelMode::AllSoundOff,
+            121 => ChannelMode::ResetAllControllers,
+            122 => ChannelMode::LocalControl(value),
+            123 => ChannelMode::AllNotesOff,
+            124 => ChannelMode::OmniOff,
+            125 => ChannelMode::OmniOn,
+            126 => ChannelMode::MonoOn(value),
+            127 => ChannelMode::PolyOn,
+            _ => return Err(PianoffError::NotChannelMode(controller)),
+        };
+
+        if mode.value() != value {
+            return Err(PianoffError::InvalidModeValue {
+                mode: mode.name(),
+                controller,
+                value,
+            });
+        }
+        mode.validate()?;
+
+        Ok(mode)
+    }
+
+    /// Checks the value rules of messages that carry a value
+    pub fn validate(&self) -> Result<(), PianoffError> {
+        match self {
+            ChannelMode::LocalControl(value) => check_range("MIDI value", *value, 127),
+            ChannelMode::MonoOn(channels) => check_range("Mono On channel count", *channels, 16),
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns the short name of the message
+    pub fn name(&self) -> &'static str {
+        match self {
+            ChannelMode::AllSoundOff => "All Sound Off",
+            ChannelMode::ResetAllControllers => "Reset All Controllers",
+            ChannelMode::LocalControl(_) => "Local Control",
+            ChannelMode::AllNotesOff => "All Notes Off",
+            ChannelMode::OmniOff => "Omni Mode Off",
+            ChannelMode::OmniOn => "Omni Mode On",
+            ChannelMode::MonoOn(_) => "Mono Mode On",
+            ChannelMode::PolyOn => "Poly Mode On",
+        }
+    }
+
+    /// Human-readable description including the value where it matters
+    pub fn describe(&self) -> String {
+        match self {
+            ChannelMode::LocalControl(value) => interpret_local_control_value(*value).to_string(),
+            ChannelMode::MonoOn(0) => "Mono Mode On (as many channels as voices)".to_string(),
+            ChannelMode::MonoOn(1) => "Mono Mode On (1 channel)".to_string(),
+            ChannelMode::MonoOn(channels) => format!("Mono Mode On ({} channels)", channels),
+            _ => self.name().to_string(),
+        }
+    }
+}
+
+/// Creates the 3-byte Control Change message for a Channel Mode message
+pub fn create_channel_mode_message(
+    mode: ChannelMode,
+    channel: u8,
+) -> Result<[u8; 3], PianoffError> {
+    mode.validate()?;
+    create_cc_message(mode.controller(), mode.value(), channel)
+}
+
+/// Interprets a Channel Mode controller and value
+/// Returns None for controllers outside 120-127, and flags values the spec does not allow
+pub fn interpret_channel_mode_value(controller: u8, value: u8) -> Option<String> {
+    match ChannelMode::from_cc(controller, value) {
+        Ok(mode) => Some(mode.describe()),
+        Err(_) if (120..=127).contains(&controller) => Some(format!(
+            "Invalid {} Value {}",
+            ChannelMode::from_cc(controller, 0)
+                .map(|mode| mode.name())
+                .unwrap_or("Channel Mode"),
+            value
+        )),
+        Err(_) => None,
+    }
+}
+
+/// Creates and sends a Channel Mode message to a sink
+/// Returns the confirmation text to display
+pub fn send_channel_mode<S: MidiSink + ?Sized>(
+    sink: &mut S,
+    mode: ChannelMode,
+    channel: u8,
+) -> Result<String, PianoffError> {
+    let midi_message = create_channel_mode_message(mode, channel)?;
+
+    sink.send(&midi_message)?;
+
+    Ok(format!(
+        "✓ Successfully sent MIDI CC #{}: {} (value: {}) on channel {}",
+        mode.controller(),
+        mode.describe(),
+        mode.value(),
+        display_channel(channel)
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RecordingSink;
+
+    #[test]
+    fn test_channel_mode_controllers_and_values() {
+        let cases = [
+            (ChannelMode::AllSoundOff, [0xB0, 120, 0]),
+            (ChannelMode::ResetAllControllers, [0xB0, 121, 0]),
+            (ChannelMode::LocalControl(127), [0xB0, 122, 127]),
+            (ChannelMode::AllNotesOff, [0xB0, 123, 0]),
+            (ChannelMode::OmniOff, [0xB0, 124, 0]),
+            (ChannelMode::OmniOn, [0xB0, 125, 0]),
+            (ChannelMode::MonoOn(4), [0xB0, 126, 4]),
+            (ChannelMode::PolyOn, [0xB0, 127, 0]),
+        ];
+
+        for (mode, expected) in cases {
+            assert_eq!(create_channel_mode_message(mode, 0).unwrap(), expected);
+            assert_eq!(
+                ChannelMode::from_cc(expected[1], expected[2]).unwrap(),
+                mode
+            );
+        }
+    }
+
+    #[test]
+    fn test_channel_mode_rejects_invalid_values() {
+        // Messages without a value must be sent with 0
+        for controller in [120, 121, 123, 124, 125, 127] {
+            let error = ChannelMode::from_cc(controller, 1).unwrap_err().to_string();
+            assert!(error.contains("Must be 0"), "Unexpected error: {}", error);
+        }
+
+        assert!(ChannelMode::from_cc(126, 16).is_ok());
+        assert!(
+            ChannelMode::from_cc(126, 17)
+                .unwrap_err()
+                .to_string()
+                .contains("Mono On")
+        );
+        assert!(create_channel_mode_message(ChannelMode::MonoOn(17), 0).is_err());
+        assert!(create_channel_mode_message(ChannelMode::LocalControl(128), 0).is_err());
+        assert!(create_channel_mode_message(ChannelMode::AllNotesOff, 16).is_err());
+
+        let error = ChannelMode::from_cc(64, 0).unwrap_err().to_string();
+        assert!(error.contains("not a Channel Mode message"));
+    }
+
+    #[test]
+    fn test_interpret_channel_mode_value() {
+        assert_eq!(
+            interpret_channel_mode_value(120, 0).as_deref(),
+            Some("All Sound Off")
+        );
+        assert_eq!(
+            interpret_channel_mode_value(122, 0).as_deref(),
+            Some("Local Control Off")
+        );
+        assert_eq!(
+            interpret_channel_mode_value(122, 64).as_deref(),
+            Some("Custom Local Control Value")
+        );
+        assert_eq!(
+            interpret_channel_mode_value(123, 0).as_deref(),
+            Some("All Notes Off")
+        );
+        assert_eq!(
+            interpret_channel_mode_value(126, 0).as_deref(),
+            Some("Mono Mode On (as many channels as voices)")
+        );
+        assert_eq!(
+            interpret_channel_mode_value(126, 2).as_deref(),
+            Some("Mono Mode On (2 channels)")
+        );
+        assert_eq!(
+            interpret_channel_mode_value(123, 5).as_deref(),
+            Some("Invalid All Notes Off Value 5")
+        );
+        assert_eq!(interpret_channel_mode_value(64, 0), None);
+    }
+
+    #[test]
+    fn test_send_channel_mode_confirmation() {
+        let mut sink = RecordingSink::new();
+        let confirmation = send_channel_mode(&mut sink, ChannelMode::AllNotesOff, 9).unwrap();
+
+        assert_eq!(sink.messages, vec![vec![0xB9, 123, 0]]);
+        assert_eq!(
+            confirmation,
+            "✓ Successfully sent MIDI CC #123: All Notes Off (value: 0) on channel 9"
+        );
+    }
+}
@@ -0,0 +1,821 @@
+use clap::error::ErrorKind;
+use clap::{Args, CommandFactory, Parser, Subcommand, ValueEnum};
+use midi_cc_sender::{
+    ChannelNumbering, DEFAULT_PPQ, MessageType, PianoffError, ValidationMode, ValidationWarning,
+    channel_numbering, controller_value_warning, parse_controller, parse_midi_value,
+};
+use std::path::PathBuf;
+
+/// Command-line interface for pianoff
+/// Running without a subcommand starts the interactive wizard
+#[derive(Debug, Parser)]
+#[command(
+    name = "pianoff",
+    version,
+    about = "Toggle Local Control (MIDI CC #122) on digital pianos"
+)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    #[command(flatten)]
+    pub wizard: WizardOptions,
+    /// Number channels 1-16 instead of 0-15 in arguments, prompts and messages
+    #[arg(long, global = true)]
+    pub one_based: bool,
+    /// Extra device profiles (TOML, or JSON with a .json extension), checked first
+    #[arg(long, value_name = "FILE", global = true)]
+    pub profiles: Option<PathBuf>,
+    /// Ask devices without a matching port profile for their SysEx identity
+    #[arg(long, global = true)]
+    pub identify: bool,
+    /// Named port/channel/value setup from the config file; flags override its values
+    /// (not to be confused with --profiles, which loads device profiles)
+    #[arg(long, value_name = "NAME", global = true)]
+    pub profile: Option<String>,
+    /// Config file to read instead of ~/.config/pianoff/config.toml
+    #[arg(long, value_name = "FILE", global = true)]
+    pub config: Option<PathBuf>,
+    /// Also write every message sent to this Standard MIDI File (format 0); not for
+    /// record or daemon
+    #[arg(long, value_name = "FILE", global = true)]
+    pub save_smf: Option<PathBuf>,
+}
+
+impl Cli {
+    /// Channel numbering selected by `--one-based`
+    pub fn channel_numbering(&self) -> ChannelNumbering {
+        if self.one_based {
+            ChannelNumbering::OneBased
+        } else {
+            ChannelNumbering::ZeroBased
+        }
+    }
+
+    /// Rejects what clap cannot check between global flags and subcommands:
+    /// `--save-smf` with `record`, which writes its own file, or with `daemon`,
+    /// which connects anew to every port it sees
+    pub fn check(&self) -> Result<(), clap::Error> {
+        let command = match &self.command {
+            Some(Command::Record { .. }) => "record",
+            Some(Command::Daemon { .. }) => "daemon",
+            _ => return Ok(()),
+        };
+        match self.save_smf {
+            Some(_) => Err(Cli::command().error(
+                ErrorKind::ArgumentConflict,
+                format!(
+                    "the argument '--save-smf <FILE>' cannot be used with '{}'",
+                    command
+                ),
+            )),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Options for the interactive wizard
+/// Subcommand arguments are always validated strictly
+#[derive(Debug, Args)]
+pub struct WizardOptions {
+    /// Reject invalid values instead of falling back to the default
+    #[arg(long)]
+    pub strict: bool,
+    /// Value used when the value prompt is left empty (0-127, on/off, 0x7F, 50%);
+    /// defaults to the last value sent, or 0
+    #[arg(long, value_name = "VALUE", value_parser = midi_value_arg)]
+    pub default_value: Option<u8>,
+    /// Channel used when the channel prompt is left empty (0-15, or 1-16 with --one-based)
+    #[arg(long, value_name = "CHANNEL", value_parser = clap::value_parser!(u8).range(0..=16))]
+    pub default_channel: Option<u8>,
+}
+
+impl WizardOptions {
+    /// Validation mode selected by `--strict`
+    pub fn mode(&self) -> ValidationMode {
+        if self.strict {
+            ValidationMode::Strict
+        } else {
+            ValidationMode::Lenient
+        }
+    }
+
+    /// Default channel as sent (0-15), read in the current channel numbering
+    /// None when not given, so the device profile or channel 0 applies
+    pub fn wire_default_channel(&self) -> Result<Option<u8>, PianoffError> {
+        wire_channel(self.default_channel)
+    }
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Turn Local Control off (value 0) so the piano only sends MIDI
+    Off {
+        #[command(flatten)]
+        target: Target,
+        #[command(flatten)]
+        broadcast: Broadcast,
+    },
+    /// Turn Local Control on (value 127) so the keys play the internal sounds
+    On {
+        #[command(flatten)]
+        target: Target,
+        #[command(flatten)]
+        broadcast: Broadcast,
+    },
+    /// Switch Local Control to the opposite of what pianoff last sent to the port
+    Toggle {
+        /// Treat Local Control as currently on or off, ignoring what was last sent
+        #[arg(long, value_name = "STATE")]
+        assume: Option<LocalState>,
+        #[command(flatten)]
+        target: Target,
+    },
+    /// Send an arbitrary CC #122 value
+    Send {
+        /// MIDI value to send: 0-127, on/off, true/false, min/max, 0x00-0x7F or 0-100%
+        #[arg(long, value_parser = value_arg)]
+        value: ValueArg,
+        #[command(flatten)]
+        target: Target,
+        #[command(flatten)]
+        broadcast: Broadcast,
+    },
+    /// Send any Control Change message, e.g. `--cc sustain --value 127`
+    Cc {
+        /// Controller number (0-127) or name (see `list-controllers`)
+        #[arg(long = "cc", value_name = "CONTROLLER", value_parser = controller_arg)]
+        controller: u8,
+        /// MIDI value to send: 0-127, on/off, true/false, min/max, 0x00-0x7F or 0-100%
+        #[arg(long, value_parser = value_arg)]
+        value: ValueArg,
+        #[command(flatten)]
+        target: Target,
+    },
+    /// Send All Sound Off (CC #120) to silence everything immediately
+    AllSoundOff(Target),
+    /// Send Reset All Controllers (CC #121)
+    ResetControllers(Target),
+    /// Send All Notes Off (CC #123) to release stuck notes
+    AllNotesOff(Target),
+    /// Send Omni Mode Off (CC #124)
+    OmniOff(Target),
+    /// Send Omni Mode On (CC #125)
+    OmniOn(Target),
+    /// Send Mono Mode On (CC #126)
+    MonoOn {
+        /// Number of mono channels (1-16), or 0 for as many as voices
+        #[arg(long, default_value_t = 0, value_parser = clap::value_parser!(u8).range(0..=16))]
+        channels: u8,
+        #[command(flatten)]
+        target: Target,
+    },
+    /// Send Poly Mode On (CC #127)
+    PolyOn(Target),
+    /// List the available MIDI output ports
+    ListPorts,
+    /// List the controller names accepted by `cc --cc`
+    ListControllers,
+    /// List the known device profiles, user profiles first
+    ListProfiles,
+    /// Ask devices for their SysEx identity and show the matching profile
+    Identify {
+        /// Only ask this port: index, exact name, case-insensitive substring or /regex/
+        #[arg(long, short)]
+        port: Option<String>,
+        /// Milliseconds to wait for each reply
+        #[arg(long, value_name = "MS", default_value_t = 1000)]
+        timeout: u64,
+    },
+    /// Print incoming MIDI messages until interrupted with Ctrl-C
+    Monitor {
+        /// Input port: index, exact name, case-insensitive substring or /regex/
+        #[arg(long, short)]
+        port: Option<String>,
+        /// Only show these message types (note, poly-aftertouch, cc, channel-mode, program,
+        /// channel-pressure, pitch-bend, sysex, common, realtime); realtime is hidden otherwise
+        #[arg(long = "type", value_name = "TYPE", value_delimiter = ',', value_parser = message_type_arg)]
+        types: Vec<MessageType>,
+        /// Only show messages on these channels (0-15, or 1-16 with --one-based)
+        #[arg(long = "channel", short, value_name = "CHANNEL", value_delimiter = ',', value_parser = clap::value_parser!(u8).range(0..=16))]
+        channels: Vec<u8>,
+        /// Print the raw bytes in hex instead of decoding them
+        #[arg(long)]
+        raw: bool,
+    },
+    /// Turn Local Control off and forward everything the piano plays to other ports
+    /// until Ctrl-C, then turn Local Control back on
+    Route {
+        /// Piano port: index, exact name, case-insensitive substring or /regex/
+        #[arg(long, short)]
+        port: Option<String>,
+        /// MIDI channel for Local Control (0-15, or 1-16 with --one-based)
+        #[arg(long, short, value_parser = clap::value_parser!(u8).range(0..=16))]
+        channel: Option<u8>,
+        /// Output port to forward to; repeat for several
+        #[arg(
+            long = "to",
+            short = 't',
+            value_name = "PORT",
+            required_unless_present = "thru"
+        )]
+        outputs: Vec<String>,
+        /// Also forward back to the piano, so it plays its own notes
+        #[arg(long)]
+        thru: bool,
+        /// Send All Notes Off before turning Local Control back on
+        #[arg(long)]
+        all_notes_off: bool,
+    },
+    /// Wait for a port to appear and send Local Control to it, every time it reconnects
+    /// Never prompts, so it can run as a service
+    Daemon {
+        /// Port name to watch for: case-insensitive substring or /regex/
+        #[arg(long, short)]
+        port: Option<String>,
+        /// Local Control value to send (default off): 0-127, on/off, true/false, min/max,
+        /// 0x00-0x7F or 0-100%
+        #[arg(long, value_parser = value_arg)]
+        value: Option<ValueArg>,
+        /// MIDI channel (0-15, or 1-16 with --one-based; defaults to the device profile's)
+        #[arg(long, short, value_parser = clap::value_parser!(u8).range(0..=16))]
+        channel: Option<u8>,
+        /// Milliseconds a port must stay listed before Local Control is sent
+        #[arg(long, value_name = "MS", default_value_t = 2000)]
+        debounce: u64,
+        /// Milliseconds between checks of the port list
+        #[arg(long, value_name = "MS", default_value_t = 500)]
+        interval: u64,
+        /// Append the action log to this file instead of printing it
+        #[arg(long, value_name = "PATH")]
+        log: Option<PathBuf>,
+    },
+    /// Inspect the config file
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+    /// Turn Local Control off and keep it off until Ctrl-C or SIGTERM; it is turned back
+    /// on however pianoff exits
+    Session {
+        /// Piano port: index, exact name, case-insensitive substring or /regex/
+        #[arg(long, short)]
+        port: Option<String>,
+        /// MIDI channel (0-15, or 1-16 with --one-based; defaults to the device profile's)
+        #[arg(long, short, value_parser = clap::value_parser!(u8).range(0..=16))]
+        channel: Option<u8>,
+        /// Send All Notes Off before turning Local Control back on
+        #[arg(long)]
+        all_notes_off: bool,
+    },
+    /// Play a Standard MIDI File (format 0 or 1) to a port, following its tempo changes
+    /// Stops early on Ctrl-C; All Notes Off is sent on the file's channels either way
+    Play {
+        /// MIDI file to play
+        #[arg(value_name = "FILE")]
+        file: PathBuf,
+        /// Output port: index, exact name, case-insensitive substring or /regex/;
+        /// without it the ports are listed to choose from
+        #[arg(long, short)]
+        port: Option<String>,
+        /// MIDI channel for Local Control (0-15, or 1-16 with --one-based; defaults to the
+        /// device profile's)
+        #[arg(long, short, value_parser = clap::value_parser!(u8).range(0..=16))]
+        channel: Option<u8>,
+        /// Turn Local Control off while playing and back on afterwards
+        #[arg(long)]
+        local_off: bool,
+    },
+    /// Record what is played on the piano to a Standard MIDI File (format 0) until Ctrl-C
+    Record {
+        /// MIDI file to write
+        #[arg(value_name = "FILE")]
+        file: PathBuf,
+        /// Input port: index, exact name, case-insensitive substring or /regex/;
+        /// without it the only port is used, or the ports are listed to choose from
+        #[arg(long, short)]
+        port: Option<String>,
+        /// MIDI channel for Local Control (0-15, or 1-16 with --one-based; defaults to the
+        /// device profile's)
+        #[arg(long, short, value_parser = clap::value_parser!(u8).range(0..=16))]
+        channel: Option<u8>,
+        /// Turn Local Control off while recording and back on afterwards, so the piano
+        /// stays silent and can be heard through the computer
+        #[arg(long)]
+        local_off: bool,
+        /// Output port to switch Local Control on with --local-off, selected like --port;
+        /// defaults to the output port named exactly like the input port
+        #[arg(long, value_name = "PORT", requires = "local_off")]
+        output_port: Option<String>,
+        /// Tempo written to the file, in beats per minute
+        #[arg(
+            long,
+            default_value_t = 120,
+            value_parser = clap::value_parser!(u32).range(4..=1000)
+        )]
+        bpm: u32,
+        /// Resolution written to the file, in ticks per quarter note
+        #[arg(
+            long,
+            default_value_t = DEFAULT_PPQ,
+            value_parser = clap::value_parser!(u16).range(1..=0x7FFF)
+        )]
+        ppq: u16,
+        /// Start the file at the first message instead of when recording started
+        #[arg(long)]
+        trim: bool,
+    },
+    /// Send System Exclusive messages given as hex or read from a .syx file
+    Sysex {
+        /// Messages as hex, e.g. `F0 41 10 42 12 40 00 7F 00 41 F7`; several may follow
+        /// each other
+        #[arg(value_name = "HEX", required_unless_present = "file")]
+        hex: Vec<String>,
+        /// .syx file holding one or more messages
+        #[arg(long, short, value_name = "FILE", conflicts_with = "hex")]
+        file: Option<PathBuf>,
+        /// Milliseconds to wait between messages, for devices with small input buffers
+        #[arg(long, value_name = "MS", default_value_t = 0)]
+        delay: u64,
+        /// Output port: index, exact name, case-insensitive substring or /regex/
+        #[arg(long, short)]
+        port: Option<String>,
+        /// Write the messages as hex to a file ("-" for stdout) instead of a MIDI port
+        #[arg(long, value_name = "PATH", conflicts_with = "port")]
+        dump: Option<PathBuf>,
+    },
+    /// Run a script of messages and waits, one command per line: `cc 122 0 ch=1`,
+    /// `program 5`, `sysex F0 43 ... F7`, `wait 50ms`; `#` starts a comment
+    Run {
+        /// Script to run
+        #[arg(value_name = "SCRIPT")]
+        file: PathBuf,
+        /// Port and channel for lines without `ch=`
+        #[command(flatten)]
+        target: Target,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ConfigAction {
+    /// Print the config file's profiles and the settings --profile and these flags give
+    Show {
+        /// Output port: index, exact name, case-insensitive substring or /regex/
+        #[arg(long, short)]
+        port: Option<String>,
+        /// MIDI channel (0-15, or 1-16 with --one-based)
+        #[arg(long, short, value_parser = clap::value_parser!(u8).range(0..=16))]
+        channel: Option<u8>,
+        /// Local Control value: 0-127, on/off, true/false, min/max, 0x00-0x7F or 0-100%
+        #[arg(long, value_parser = value_arg)]
+        value: Option<ValueArg>,
+    },
+}
+
+/// Output port and channel shared by every sending subcommand
+#[derive(Debug, Args)]
+pub struct Target {
+    /// Output port: index, exact name, case-insensitive substring or /regex/
+    #[arg(long, short)]
+    pub port: Option<String>,
+    /// MIDI channel (0-15, or 1-16 with --one-based; defaults to the device profile's)
+    #[arg(long, short, value_parser = clap::value_parser!(u8).range(0..=16))]
+    pub channel: Option<u8>,
+    /// Write the messages as hex to a file ("-" for stdout) instead of a MIDI port
+    #[arg(long, value_name = "PATH", conflicts_with = "port")]
+    pub dump: Option<PathBuf>,
+}
+
+impl Target {
+    /// Channel as sent (0-15), read in the current channel numbering
+    /// None when not given, so the device profile or channel 0 applies
+    pub fn wire_channel(&self) -> Result<Option<u8>, PianoffError> {
+        wire_channel(self.channel)
+    }
+}
+
+/// Converts a channel argument to the wire channel
+pub fn wire_channel(channel: Option<u8>) -> Result<Option<u8>, PianoffError> {
+    channel
+        .map(|number| channel_numbering().from_display(number))
+        .transpose()
+}
+
+/// Options for sending Local Control on every channel at once
+#[derive(Debug, Args)]
+pub struct Broadcast {
+    /// Send on all 16 channels instead of --channel
+    #[arg(long, conflicts_with = "channel")]
+    pub all_channels: bool,
+    /// Milliseconds to wait between messages when broadcasting
+    #[arg(
+        long,
+        value_name = "MS",
+        default_value_t = 0,
+        requires = "all_channels"
+    )]
+    pub delay: u64,
+}
+
+/// Local Control state named on the command line
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum LocalState {
+    On,
+    Off,
+}
+
+impl LocalState {
+    /// The CC #122 value for this state
+    pub fn value(self) -> u8 {
+        match self {
+            LocalState::On => 127,
+            LocalState::Off => 0,
+        }
+    }
+}
+
+/// A MIDI value given on the command line, with any ambiguity warning to show
+#[derive(Debug, Clone)]
+pub struct ValueArg {
+    pub value: u8,
+    pub warning: Option<ValidationWarning>,
+}
+
+impl ValueArg {
+    /// Keeps the warning only if it applies to a value for `controller`
+    pub fn for_controller(self, controller: u8) -> Self {
+        ValueArg {
+            warning: controller_value_warning(self.warning, controller),
+            ..self
+        }
+    }
+}
+
+fn value_arg(input: &str) -> Result<ValueArg, String> {
+    parse_midi_value(input)
+        .map(|(value, warning)| ValueArg { value, warning })
+        .map_err(|warning| warning.into_error().to_string())
+}
+
+fn midi_value_arg(input: &str) -> Result<u8, String> {
+    value_arg(input).map(|arg| arg.value)
+}
+
+fn controller_arg(input: &str) -> Result<u8, String> {
+    parse_controller(input).map_err(|e| e.to_string())
+}
+
+fn message_type_arg(input: &str) -> Result<MessageType, String> {
+    input.parse().map_err(|e: PianoffError| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_no_arguments_selects_interactive_mode() {
+        let cli = Cli::try_parse_from(["pianoff"]).unwrap();
+        assert!(cli.command.is_none());
+    }
+
+    #[test]
+    fn test_wizard_options() {
+        let cli = Cli::try_parse_from(["pianoff"]).unwrap();
+        assert_eq!(cli.wizard.mode(), ValidationMode::Lenient);
+        assert_eq!(cli.wizard.default_value, None);
+        assert_eq!(cli.wizard.wire_default_channel().unwrap(), None);
+
+        let cli = Cli::try_parse_from(["pianoff", "--strict", "--default-value", "127"]).unwrap();
+        assert_eq!(cli.wizard.mode(), ValidationMode::Strict);
+        assert_eq!(cli.wizard.default_value, Some(127));
+
+        assert!(Cli::try_parse_from(["pianoff", "--default-channel", "17"]).is_err());
+    }
+
+    #[test]
+    fn test_send_subcommand_arguments() {
+        let cli = Cli::try_parse_from([
+            "pianoff",
+            "send",
+            "--value",
+            "64",
+            "--channel",
+            "3",
+            "--port",
+            "P-125",
+        ])
+        .unwrap();
+
+        match cli.command {
+            Some(Command::Send { value, target, .. }) => {
+                assert_eq!(value.value, 64);
+                assert!(value.warning.is_none());
+                assert_eq!(target.wire_channel().unwrap(), Some(3));
+                assert_eq!(target.port.as_deref(), Some("P-125"));
+            }
+            other => panic!("Unexpected command: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_value_arguments_accept_extended_forms() {
+        for (input, expected) in [("on", 127), ("off", 0), ("0x40", 64), ("50%", 64)] {
+            let cli = Cli::try_parse_from(["pianoff", "send", "--value", input]).unwrap();
+            match cli.command {
+                Some(Command::Send { value, .. }) => assert_eq!(value.value, expected),
+                other => panic!("Unexpected command: {:?}", other),
+            }
+        }
+
+        let cli = Cli::try_parse_from(["pianoff", "send", "--value", "1"]).unwrap();
+        match cli.command {
+            Some(Command::Send { value, .. }) => assert!(value.warning.is_some()),
+            other => panic!("Unexpected command: {:?}", other),
+        }
+
+        let cli = Cli::try_parse_from(["pianoff", "--default-value", "on"]).unwrap();
+        assert_eq!(cli.wizard.default_value, Some(127));
+    }
+
+    #[test]
+    fn test_cc_subcommand_accepts_controller_names() {
+        let cli =
+            Cli::try_parse_from(["pianoff", "cc", "--cc", "sustain", "--value", "127"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Command::Cc {
+                controller: 64,
+                value: ValueArg { value: 127, .. },
+                ..
+            })
+        ));
+
+        let cli = Cli::try_parse_from(["pianoff", "cc", "--cc", "91", "--value", "40"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Command::Cc { controller: 91, .. })
+        ));
+
+        assert!(Cli::try_parse_from(["pianoff", "cc", "--cc", "kazoo", "--value", "1"]).is_err());
+
+        // A bare 1 only hints at 'on' for switch controllers
+        let cli = Cli::try_parse_from(["pianoff", "cc", "--cc", "volume", "--value", "1"]).unwrap();
+        match cli.command {
+            Some(Command::Cc {
+                controller, value, ..
+            }) => {
+                assert!(value.warning.is_some());
+                let value = value.for_controller(controller);
+                assert_eq!((value.value, value.warning), (1, None));
+            }
+            other => panic!("Unexpected command: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_all_channels_broadcast_options() {
+        let cli =
+            Cli::try_parse_from(["pianoff", "off", "--all-channels", "--delay", "20"]).unwrap();
+        match cli.command {
+            Some(Command::Off { broadcast, .. }) => {
+                assert!(broadcast.all_channels);
+                assert_eq!(broadcast.delay, 20);
+            }
+            other => panic!("Unexpected command: {:?}", other),
+        }
+
+        assert!(Cli::try_parse_from(["pianoff", "on", "--all-channels", "-c", "3"]).is_err());
+        assert!(Cli::try_parse_from(["pianoff", "on", "--delay", "20"]).is_err());
+    }
+
+    #[test]
+    fn test_channel_mode_subcommands() {
+        let cli = Cli::try_parse_from(["pianoff", "all-notes-off", "-c", "2"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Command::AllNotesOff(Target {
+                channel: Some(2),
+                ..
+            }))
+        ));
+
+        let cli = Cli::try_parse_from(["pianoff", "mono-on", "--channels", "4"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Command::MonoOn { channels: 4, .. })
+        ));
+
+        assert!(Cli::try_parse_from(["pianoff", "mono-on", "--channels", "17"]).is_err());
+    }
+
+    #[test]
+    fn test_out_of_range_arguments_are_rejected() {
+        assert!(Cli::try_parse_from(["pianoff", "send", "--value", "128"]).is_err());
+        assert!(Cli::try_parse_from(["pianoff", "send", "--value", "onn"]).is_err());
+        assert!(Cli::try_parse_from(["pianoff", "off", "--channel", "17"]).is_err());
+    }
+
+    #[test]
+    fn test_monitor_filters() {
+        let cli = Cli::try_parse_from([
+            "pianoff",
+            "monitor",
+            "--type",
+            "note,channel-mode",
+            "-c",
+            "1",
+            "-c",
+            "9",
+            "--raw",
+        ])
+        .unwrap();
+
+        match cli.command {
+            Some(Command::Monitor {
+                types,
+                channels,
+                raw,
+                ..
+            }) => {
+                assert_eq!(types, vec![MessageType::Note, MessageType::ChannelMode]);
+                assert_eq!(channels, vec![1, 9]);
+                assert!(raw);
+            }
+            other => panic!("Unexpected command: {:?}", other),
+        }
+
+        assert!(Cli::try_parse_from(["pianoff", "monitor", "--type", "kazoo"]).is_err());
+    }
+
+    #[test]
+    fn test_route_outputs() {
+        let cli = Cli::try_parse_from([
+            "pianoff", "route", "-p", "P-125", "--to", "DAW", "-t", "/Synth/",
+        ])
+        .unwrap();
+
+        match cli.command {
+            Some(Command::Route {
+                port,
+                outputs,
+                thru,
+                ..
+            }) => {
+                assert_eq!(port.as_deref(), Some("P-125"));
+                assert_eq!(outputs, vec!["DAW", "/Synth/"]);
+                assert!(!thru);
+            }
+            other => panic!("Unexpected command: {:?}", other),
+        }
+
+        assert!(Cli::try_parse_from(["pianoff", "route", "--thru"]).is_ok());
+        assert!(Cli::try_parse_from(["pianoff", "route", "-p", "P-125"]).is_err());
+    }
+
+    #[test]
+    fn test_daemon_defaults() {
+        let cli = Cli::try_parse_from(["pianoff", "daemon", "--port", "P-125"]).unwrap();
+
+        match cli.command {
+            Some(Command::Daemon {
+                port,
+                value,
+                debounce,
+                interval,
+                log,
+                ..
+            }) => {
+                assert_eq!(port.as_deref(), Some("P-125"));
+                assert!(value.is_none());
+                assert_eq!((debounce, interval), (2000, 500));
+                assert!(log.is_none());
+            }
+            other => panic!("Unexpected command: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_record_defaults() {
+        let cli = Cli::try_parse_from(["pianoff", "record", "out.mid", "--trim"]).unwrap();
+        match cli.command {
+            Some(Command::Record {
+                file,
+                bpm,
+                ppq,
+                trim,
+                local_off,
+                output_port,
+                ..
+            }) => {
+                assert_eq!(file, PathBuf::from("out.mid"));
+                assert_eq!((bpm, ppq), (120, 480));
+                assert!(trim);
+                assert!(!local_off);
+                assert_eq!(output_port, None);
+            }
+            other => panic!("Unexpected command: {:?}", other),
+        }
+
+        let cli = Cli::try_parse_from([
+            "pianoff",
+            "record",
+            "out.mid",
+            "--local-off",
+            "--output-port",
+            "P-125 Out",
+        ])
+        .unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Command::Record { output_port: Some(ref port), .. }) if port == "P-125 Out"
+        ));
+        assert!(
+            Cli::try_parse_from(["pianoff", "record", "out.mid", "--output-port", "1"]).is_err()
+        );
+
+        assert!(Cli::try_parse_from(["pianoff", "record"]).is_err());
+        assert!(Cli::try_parse_from(["pianoff", "record", "out.mid", "--ppq", "0"]).is_err());
+        assert!(Cli::try_parse_from(["pianoff", "record", "out.mid", "--bpm", "3"]).is_err());
+    }
+
+    #[test]
+    fn test_save_smf_conflicts() {
+        let cli = Cli::try_parse_from(["pianoff", "session", "--save-smf", "out.mid"]).unwrap();
+        assert!(cli.check().is_ok());
+
+        for args in [&["record", "take.mid"][..], &["daemon", "-p", "P-125"]] {
+            let cli = Cli::try_parse_from(["pianoff", "--save-smf", "out.mid"].iter().chain(args))
+                .unwrap();
+            let error = cli.check().unwrap_err();
+            assert_eq!(error.kind(), ErrorKind::ArgumentConflict);
+        }
+    }
+
+    #[test]
+    fn test_sysex_sources() {
+        let cli = Cli::try_parse_from(["pianoff", "sysex", "F0 43 10", "4C", "F7"]).unwrap();
+        match cli.command {
+            Some(Command::Sysex {
+                hex, file, delay, ..
+            }) => {
+                assert_eq!(hex, vec!["F0 43 10", "4C", "F7"]);
+                assert_eq!(file, None);
+                assert_eq!(delay, 0);
+            }
+            other => panic!("Unexpected command: {:?}", other),
+        }
+
+        assert!(
+            Cli::try_parse_from(["pianoff", "sysex", "-f", "patch.syx", "--delay", "20"]).is_ok()
+        );
+        assert!(Cli::try_parse_from(["pianoff", "sysex"]).is_err());
+        assert!(Cli::try_parse_from(["pianoff", "sysex", "F0 F7", "-f", "patch.syx"]).is_err());
+    }
+
+    #[test]
+    fn test_profile_is_global() {
+        let cli =
+            Cli::try_parse_from(["pianoff", "off", "--profile", "studio", "-c", "3"]).unwrap();
+        assert_eq!(cli.profile.as_deref(), Some("studio"));
+
+        let cli =
+            Cli::try_parse_from(["pianoff", "--profile", "studio", "config", "show"]).unwrap();
+        assert_eq!(cli.profile.as_deref(), Some("studio"));
+        assert!(matches!(
+            cli.command,
+            Some(Command::Config {
+                action: ConfigAction::Show { .. }
+            })
+        ));
+    }
+
+    #[test]
+    fn test_toggle_assume() {
+        let cli = Cli::try_parse_from(["pianoff", "toggle", "--assume", "off", "-p", "1"]).unwrap();
+        match cli.command {
+            Some(Command::Toggle { assume, target }) => {
+                assert_eq!(assume.map(LocalState::value), Some(0));
+                assert_eq!(target.port.as_deref(), Some("1"));
+            }
+            other => panic!("Unexpected command: {:?}", other),
+        }
+
+        assert!(Cli::try_parse_from(["pianoff", "toggle", "--assume", "maybe"]).is_err());
+    }
+
+    #[test]
+    fn test_one_based_flag() {
+        let cli = Cli::try_parse_from(["pianoff", "off", "-c", "16", "--one-based"]).unwrap();
+        assert_eq!(cli.channel_numbering(), ChannelNumbering::OneBased);
+        match cli.command {
+            Some(Command::Off { target, .. }) => assert_eq!(target.channel, Some(16)),
+            other => panic!("Unexpected command: {:?}", other),
+        }
+
+        let cli = Cli::try_parse_from(["pianoff", "--one-based"]).unwrap();
+        assert_eq!(cli.channel_numbering(), ChannelNumbering::OneBased);
+
+        let cli = Cli::try_parse_from(["pianoff", "off"]).unwrap();
+        assert_eq!(cli.channel_numbering(), ChannelNumbering::ZeroBased);
+    }
+}
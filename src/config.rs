@@ -0,0 +1,211 @@
+use crate::{PianoffError, interpret_local_control_value, parse_midi_value};
+use serde::{Deserialize, Deserializer};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+/// A named port/channel/value combination from `config.toml`, selected with `--profile`
+/// Every field is optional; command-line flags take precedence over it
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Setup {
+    /// Output port selector: index, exact name, substring or `/regex/`
+    pub port: Option<String>,
+    /// Channel as sent, 0-15
+    pub channel: Option<u8>,
+    /// Local Control value: `"off"`, `"on"`, a number or anything `parse_midi_value` accepts
+    #[serde(default, deserialize_with = "local_value")]
+    pub local: Option<u8>,
+}
+
+impl Setup {
+    /// Keeps every field set here and takes the rest from `fallback`
+    pub fn or(self, fallback: &Setup) -> Setup {
+        Setup {
+            port: self.port.or_else(|| fallback.port.clone()),
+            channel: self.channel.or(fallback.channel),
+            local: self.local.or(fallback.local),
+        }
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        match self.channel {
+            Some(channel) if channel > 15 => Err(format!(
+                "channel {} is out of range (0-15, as sent)",
+                channel
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl fmt::Display for Setup {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut fields = Vec::new();
+        if let Some(port) = &self.port {
+            fields.push(format!("port = {:?}", port));
+        }
+        if let Some(channel) = self.channel {
+            fields.push(format!("channel = {}", channel));
+        }
+        if let Some(local) = self.local {
+            fields.push(format!(
+                "local = {} ({})",
+                local,
+                interpret_local_control_value(local)
+            ));
+        }
+        write!(f, "{{ {} }}", fields.join(", "))
+    }
+}
+
+/// Accepts `local = "off"` as well as `local = 0`
+fn local_value<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u8>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Raw {
+        Number(i64),
+        Text(String),
+    }
+
+    let text = match Raw::deserialize(deserializer)? {
+        Raw::Number(number) => number.to_string(),
+        Raw::Text(text) => text,
+    };
+    parse_midi_value(&text)
+        .map(|(value, _)| Some(value))
+        .map_err(|warning| serde::de::Error::custom(warning.into_error()))
+}
+
+/// Contents of `config.toml`: setups keyed by name, e.g.
+/// `studio = { port = "P-125", channel = 0, local = "off" }`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(transparent)]
+pub struct Config {
+    pub setups: BTreeMap<String, Setup>,
+}
+
+impl Config {
+    /// Parses a configuration written as TOML; `origin` names it in errors
+    pub fn from_toml(text: &str, origin: &str) -> Result<Self, PianoffError> {
+        let invalid = |reason: String| PianoffError::Config {
+            origin: origin.to_string(),
+            reason,
+        };
+
+        let config: Config = toml::from_str(text).map_err(|e| invalid(e.message().to_string()))?;
+        for (name, setup) in &config.setups {
+            setup
+                .validate()
+                .map_err(|reason| invalid(format!("profile '{}': {}", name, reason)))?;
+        }
+        Ok(config)
+    }
+
+    /// Reads a configuration file
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, PianoffError> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path).map_err(|e| {
+            PianoffError::io(format!("Failed to read config '{}'", path.display()), e)
+        })?;
+        Config::from_toml(&text, &format!("'{}'", path.display()))
+    }
+
+    /// Looks up a setup by name
+    pub fn setup(&self, name: &str) -> Result<&Setup, PianoffError> {
+        self.setups
+            .get(name)
+            .ok_or_else(|| PianoffError::UnknownSetup {
+                name: name.to_string(),
+                available: self.setups.keys().cloned().collect(),
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"
+studio = { port = "P-125", channel = 0, local = "off" }
+home = { local = 127 }
+
+[stage]
+port = "/^Stage/"
+local = "50%"
+"#;
+
+    #[test]
+    fn test_parse_config() {
+        let config = Config::from_toml(CONFIG, "test").unwrap();
+
+        assert_eq!(
+            config.setup("studio").unwrap(),
+            &Setup {
+                port: Some("P-125".to_string()),
+                channel: Some(0),
+                local: Some(0),
+            }
+        );
+        assert_eq!(config.setup("home").unwrap().local, Some(127));
+        assert_eq!(config.setup("stage").unwrap().local, Some(64));
+        assert_eq!(
+            config.setup("studio").unwrap().to_string(),
+            "{ port = \"P-125\", channel = 0, local = 0 (Local Control Off) }"
+        );
+
+        let error = config.setup("office").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "No profile named 'office' in the configuration; available: 'home', 'stage', 'studio'"
+        );
+    }
+
+    #[test]
+    fn test_reject_invalid_config() {
+        let cases = [
+            (
+                "studio = { port = \"P-125\", volume = 3 }",
+                "unknown field `volume`",
+            ),
+            (
+                "studio = { channel = 16 }",
+                "profile 'studio': channel 16 is out of range",
+            ),
+            ("studio = { local = \"loud\" }", "Invalid MIDI value 'loud'"),
+        ];
+
+        for (text, expected) in cases {
+            let error = Config::from_toml(text, "'config.toml'").unwrap_err();
+            assert!(
+                error.to_string().contains(expected),
+                "{:?} does not mention {:?}",
+                error.to_string(),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_command_line_overrides_setup() {
+        let setup = Setup {
+            port: Some("P-125".to_string()),
+            channel: Some(0),
+            local: Some(0),
+        };
+        let flags = Setup {
+            channel: Some(3),
+            ..Setup::default()
+        };
+
+        assert_eq!(
+            flags.or(&setup),
+            Setup {
+                port: Some("P-125".to_string()),
+                channel: Some(3),
+                local: Some(0),
+            }
+        );
+    }
+}
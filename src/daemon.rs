@@ -0,0 +1,302 @@
+use crate::{PianoffError, PortMatcher};
+use std::collections::HashMap;
+use std::fmt;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// A port told apart from others of the same name, as when two identical devices
+/// are connected: `occurrence` counts the ports listed before it with that name
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct WatchedPort {
+    pub name: String,
+    pub occurrence: usize,
+}
+
+impl WatchedPort {
+    /// The first port with this name
+    pub fn new(name: impl Into<String>) -> Self {
+        WatchedPort {
+            name: name.into(),
+            occurrence: 0,
+        }
+    }
+
+    /// Finds this port in a port list
+    pub fn find<S: AsRef<str>>(&self, names: &[S]) -> Option<usize> {
+        names
+            .iter()
+            .enumerate()
+            .filter(|(_, name)| name.as_ref() == self.name)
+            .map(|(index, _)| index)
+            .nth(self.occurrence)
+    }
+}
+
+impl fmt::Display for WatchedPort {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.occurrence {
+            0 => write!(f, "{}", self.name),
+            occurrence => write!(f, "{} (#{})", self.name, occurrence + 1),
+        }
+    }
+}
+
+/// A change the daemon noticed in the port list
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PortEvent {
+    /// A matching port showed up; it is configured once it has settled
+    Appeared(WatchedPort),
+    /// A matching port has been present for the whole debounce time
+    Ready(WatchedPort),
+    /// A matching port went away
+    Disappeared(WatchedPort),
+}
+
+impl fmt::Display for PortEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PortEvent::Appeared(port) => write!(f, "Port appeared: {}", port),
+            PortEvent::Ready(port) => write!(f, "Port ready: {}", port),
+            PortEvent::Disappeared(port) => write!(f, "Port disappeared: {}", port),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Presence {
+    Settling(Instant),
+    Ready,
+}
+
+/// Follows the ports whose names match a selector across successive port lists
+/// A port becomes ready once it has been listed for `debounce`, so a device that
+/// reconnects several times while powering up is configured only once it is stable
+#[derive(Debug)]
+pub struct PortWatcher {
+    matcher: PortMatcher,
+    debounce: Duration,
+    ports: HashMap<WatchedPort, Presence>,
+}
+
+impl PortWatcher {
+    /// Watches for ports matching `selector`: a case-insensitive substring or `/regex/`
+    /// A number is matched as part of the name, since positions change on hotplug
+    pub fn new(selector: &str, debounce: Duration) -> Result<Self, PianoffError> {
+        let matcher = match PortMatcher::parse(selector)? {
+            PortMatcher::Index(_) => PortMatcher::Name(selector.trim().to_string()),
+            matcher => matcher,
+        };
+        Ok(PortWatcher {
+            matcher,
+            debounce,
+            ports: HashMap::new(),
+        })
+    }
+
+    /// Compares a fresh port list with the previous ones and reports what changed
+    pub fn update<S: AsRef<str>>(&mut self, names: &[S], now: Instant) -> Vec<PortEvent> {
+        let mut events = Vec::new();
+
+        let mut listed: Vec<WatchedPort> = Vec::new();
+        for name in names.iter().map(AsRef::as_ref) {
+            if self.matcher.matches(name) {
+                let occurrence = listed.iter().filter(|port| port.name == name).count();
+                listed.push(WatchedPort {
+                    name: name.to_string(),
+                    occurrence,
+                });
+            }
+        }
+
+        let mut gone: Vec<WatchedPort> = self
+            .ports
+            .keys()
+            .filter(|port| !listed.contains(port))
+            .cloned()
+            .collect();
+        gone.sort();
+        for port in gone {
+            self.ports.remove(&port);
+            events.push(PortEvent::Disappeared(port));
+        }
+
+        for port in listed {
+            match self.ports.get(&port).copied() {
+                None => {
+                    self.ports.insert(port.clone(), Presence::Settling(now));
+                    events.push(PortEvent::Appeared(port.clone()));
+                    if self.debounce.is_zero() {
+                        self.ports.insert(port.clone(), Presence::Ready);
+                        events.push(PortEvent::Ready(port));
+                    }
+                }
+                Some(Presence::Settling(since)) if now.duration_since(since) >= self.debounce => {
+                    self.ports.insert(port.clone(), Presence::Ready);
+                    events.push(PortEvent::Ready(port));
+                }
+                Some(_) => {}
+            }
+        }
+
+        events
+    }
+
+    /// Makes a ready port settle again, so it is retried after another debounce
+    pub fn retry(&mut self, port: &WatchedPort, now: Instant) {
+        if let Some(presence) = self.ports.get_mut(port) {
+            *presence = Presence::Settling(now);
+        }
+    }
+}
+
+/// Prefixes a log message with the UTC time, e.g. `2024-03-01T12:30:05Z Port ready: P-125`
+pub fn format_log_line(time: SystemTime, message: &str) -> String {
+    let seconds = time
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or(0);
+    let (days, seconds) = (seconds / 86_400, seconds % 86_400);
+    let (year, month, day) = civil_date(days);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z {}",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60,
+        message
+    )
+}
+
+/// Converts days since 1970-01-01 to a Gregorian (year, month, day)
+fn civil_date(days: u64) -> (u64, u64, u64) {
+    // Shifted so that years start in March, which puts leap days at the end
+    let days = days + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = era * 400 + year_of_era + u64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_port_becomes_ready_after_debounce() {
+        let start = Instant::now();
+        let mut watcher = PortWatcher::new("p-125", Duration::from_millis(1000)).unwrap();
+        let ports = ["Midi Through", "P-125 MIDI 1"];
+
+        assert_eq!(
+            watcher.update(&ports, start),
+            vec![PortEvent::Appeared(WatchedPort::new("P-125 MIDI 1"))]
+        );
+        assert!(
+            watcher
+                .update(&ports, start + Duration::from_millis(500))
+                .is_empty()
+        );
+        assert_eq!(
+            watcher.update(&ports, start + Duration::from_millis(1000)),
+            vec![PortEvent::Ready(WatchedPort::new("P-125 MIDI 1"))]
+        );
+        assert!(
+            watcher
+                .update(&ports, start + Duration::from_millis(5000))
+                .is_empty()
+        );
+
+        watcher.retry(
+            &WatchedPort::new("P-125 MIDI 1"),
+            start + Duration::from_millis(5000),
+        );
+        assert_eq!(
+            watcher.update(&ports, start + Duration::from_millis(6000)),
+            vec![PortEvent::Ready(WatchedPort::new("P-125 MIDI 1"))]
+        );
+    }
+
+    #[test]
+    fn test_reconnect_restarts_debounce() {
+        let start = Instant::now();
+        let mut watcher = PortWatcher::new("/^P-125/", Duration::from_millis(1000)).unwrap();
+        let at = |ms| start + Duration::from_millis(ms);
+
+        watcher.update(&["P-125"], at(0));
+        assert_eq!(
+            watcher.update(&["Other"], at(400)),
+            vec![PortEvent::Disappeared(WatchedPort::new("P-125"))]
+        );
+        assert_eq!(
+            watcher.update(&["P-125"], at(800)),
+            vec![PortEvent::Appeared(WatchedPort::new("P-125"))]
+        );
+        assert!(watcher.update(&["P-125"], at(1200)).is_empty());
+        assert_eq!(
+            watcher.update(&["P-125"], at(1800)),
+            vec![PortEvent::Ready(WatchedPort::new("P-125"))]
+        );
+
+        let mut immediate = PortWatcher::new("125", Duration::ZERO).unwrap();
+        assert_eq!(
+            immediate.update(&["P-125"], at(0)),
+            vec![
+                PortEvent::Appeared(WatchedPort::new("P-125")),
+                PortEvent::Ready(WatchedPort::new("P-125"))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_identical_ports_are_told_apart() {
+        let start = Instant::now();
+        let mut watcher = PortWatcher::new("p-125", Duration::ZERO).unwrap();
+        let second = WatchedPort {
+            name: "P-125".to_string(),
+            occurrence: 1,
+        };
+
+        watcher.update(&["P-125"], start);
+        assert_eq!(
+            watcher.update(&["P-125", "Other", "P-125"], start),
+            vec![
+                PortEvent::Appeared(second.clone()),
+                PortEvent::Ready(second.clone())
+            ]
+        );
+        assert_eq!(second.to_string(), "P-125 (#2)");
+        assert_eq!(second.find(&["P-125", "Other", "P-125"]), Some(2));
+        assert_eq!(second.find(&["P-125"]), None);
+
+        assert_eq!(
+            watcher.update(&["P-125"], start),
+            vec![PortEvent::Disappeared(second)]
+        );
+    }
+
+    #[test]
+    fn test_format_log_line() {
+        let time = UNIX_EPOCH + Duration::from_secs(1_709_296_205);
+        assert_eq!(
+            format_log_line(time, "Port ready: P-125"),
+            "2024-03-01T12:30:05Z Port ready: P-125"
+        );
+        assert_eq!(format_log_line(UNIX_EPOCH, "x"), "1970-01-01T00:00:00Z x");
+        assert_eq!(
+            format_log_line(UNIX_EPOCH + Duration::from_secs(951_782_400), "x"),
+            "2000-02-29T00:00:00Z x"
+        );
+    }
+}
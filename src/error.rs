@@ -0,0 +1,517 @@
+use crate::display_channel;
+use crate::message::MidiMessageError;
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::time::Duration;
+
+/// Every error pianoff can report
+#[derive(Debug)]
+pub enum PianoffError {
+    /// A numeric field is outside its allowed range, e.g. a MIDI value above 127
+    OutOfRange {
+        field: &'static str,
+        value: u32,
+        min: u32,
+        max: u32,
+    },
+    /// Text that could not be parsed as the expected field
+    Parse { field: &'static str, input: String },
+    /// Controller name that is not in `KNOWN_CONTROLLERS`
+    UnknownController(String),
+    /// Controller number outside the Channel Mode range 120-127
+    NotChannelMode(u8),
+    /// Channel Mode message sent with a value other than the one it requires
+    InvalidModeValue {
+        mode: &'static str,
+        controller: u8,
+        value: u8,
+    },
+    /// Malformed MIDI bytes or message fields
+    Message(MidiMessageError),
+    /// No MIDI ports exist at all
+    NoPorts,
+    /// A port index past the end of the port list
+    PortIndexOutOfRange { index: usize, count: usize },
+    /// A port selector that matches no port
+    PortNotFound {
+        selector: String,
+        available: Vec<String>,
+    },
+    /// A port selector that matches more than one port
+    AmbiguousPort {
+        selector: String,
+        matches: Vec<String>,
+    },
+    /// A port selector that is empty or not a valid regex
+    InvalidPortSelector { selector: String, reason: String },
+    /// Several ports exist and none was chosen
+    PortRequired { available: Vec<String> },
+    /// No output port has the same name as the input port a device was found on
+    NoOutputForInput {
+        input: String,
+        available: Vec<String>,
+    },
+    /// The MIDI backend (ALSA, CoreMIDI, WinMM) could not be used
+    Backend(String),
+    /// Connecting to a port failed
+    Connect { port: String, reason: String },
+    /// The transport refused a message
+    Send(String),
+    /// Sending on several channels stopped part-way through
+    BatchSend {
+        channel: u8,
+        sent: Vec<u8>,
+        reason: String,
+    },
+    /// File or terminal I/O failed
+    Io { context: String, source: io::Error },
+    /// A device profile file could not be read or describes an invalid profile
+    Profile { origin: String, reason: String },
+    /// The device's profile says it cannot switch Local Control this way
+    LocalControlUnsupported { device: String, reason: String },
+    /// A SysEx message that should have been an Identity Reply
+    InvalidIdentityReply { bytes: String, reason: &'static str },
+    /// The device did not answer in time
+    NoReply { timeout: Duration },
+    /// No input port belongs to the output port, so replies cannot be read
+    NoInputPort {
+        port: String,
+        available: Vec<String>,
+    },
+    /// The SIGINT/SIGTERM handler could not be installed
+    Signal(String),
+    /// The configuration file could not be parsed or holds an invalid setup
+    Config { origin: String, reason: String },
+    /// `--profile` names a setup the configuration does not define
+    UnknownSetup {
+        name: String,
+        available: Vec<String>,
+    },
+    /// A command has no port from the command line or the selected profile
+    MissingPort { command: &'static str },
+    /// The state file exists but cannot be understood
+    State { path: String, reason: String },
+    /// A script line that cannot be parsed; line and column count from 1
+    Script {
+        origin: String,
+        line: usize,
+        column: usize,
+        reason: String,
+    },
+    /// SysEx bytes, from a file or the command line, that are not valid messages
+    InvalidSysEx {
+        origin: String,
+        offset: usize,
+        reason: String,
+    },
+    /// A Standard MIDI File that is malformed or of an unsupported kind
+    Smf {
+        origin: String,
+        offset: usize,
+        reason: String,
+    },
+}
+
+impl PianoffError {
+    /// Wraps an I/O error with a description of what was being attempted
+    pub fn io(context: impl Into<String>, source: io::Error) -> Self {
+        PianoffError::Io {
+            context: context.into(),
+            source,
+        }
+    }
+}
+
+impl fmt::Display for PianoffError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PianoffError::OutOfRange {
+                field,
+                value,
+                min,
+                max,
+            } => write!(f, "Invalid {}: {}. Must be {}-{}.", field, value, min, max),
+            PianoffError::Parse { field, input } => write!(f, "Invalid {} '{}'", field, input),
+            PianoffError::UnknownController(name) => write!(
+                f,
+                "Unknown controller '{}'. Use a number (0-127) or a known name.",
+                name
+            ),
+            PianoffError::NotChannelMode(controller) => write!(
+                f,
+                "Controller {} is not a Channel Mode message. Must be 120-127.",
+                controller
+            ),
+            PianoffError::InvalidModeValue {
+                mode,
+                controller,
+                value,
+            } => write!(
+                f,
+                "Invalid value {} for {} (CC #{}). Must be 0.",
+                value, mode, controller
+            ),
+            PianoffError::Message(error) => write!(f, "{}", error),
+            PianoffError::NoPorts => write!(f, "No MIDI ports available."),
+            PianoffError::PortIndexOutOfRange { index, count } => write!(
+                f,
+                "Invalid port selection: Port {} does not exist. Available ports: 0-{}",
+                index,
+                count.saturating_sub(1)
+            ),
+            PianoffError::PortNotFound {
+                selector,
+                available,
+            } => write!(
+                f,
+                "No MIDI port matches '{}'. Available ports: {}",
+                selector,
+                quoted_list(available)
+            ),
+            PianoffError::AmbiguousPort { selector, matches } => write!(
+                f,
+                "Port selector '{}' is ambiguous, it matches: {}",
+                selector,
+                quoted_list(matches)
+            ),
+            PianoffError::InvalidPortSelector { selector, reason } => {
+                write!(f, "Invalid port pattern '{}': {}", selector, reason)
+            }
+            PianoffError::PortRequired { available } => write!(
+                f,
+                "Several MIDI ports are available; choose one with --port: {}",
+                quoted_list(available)
+            ),
+            PianoffError::NoOutputForInput { input, available } => write!(
+                f,
+                "No MIDI output port is named '{}' like the input port; choose one with \
+                 --output-port: {}",
+                input,
+                quoted_list(available)
+            ),
+            PianoffError::Backend(reason) => write!(f, "{}", reason),
+            PianoffError::Connect { port, reason } => {
+                write!(f, "Failed to connect to MIDI port '{}': {}", port, reason)
+            }
+            PianoffError::Send(reason) => write!(f, "Failed to send MIDI message: {}", reason),
+            PianoffError::BatchSend {
+                channel,
+                sent,
+                reason,
+            } => {
+                let sent = if sent.is_empty() {
+                    "none".to_string()
+                } else {
+                    sent.iter()
+                        .map(|channel| display_channel(*channel).to_string())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                };
+                write!(
+                    f,
+                    "Failed to send MIDI message on channel {} (already sent on channels: {}): {}",
+                    display_channel(*channel),
+                    sent,
+                    reason
+                )
+            }
+            PianoffError::Io { context, source } => write!(f, "{}: {}", context, source),
+            PianoffError::Profile { origin, reason } => {
+                write!(f, "Invalid device profiles in {}: {}", origin, reason)
+            }
+            PianoffError::LocalControlUnsupported { device, reason } => {
+                write!(f, "{} cannot switch Local Control: {}", device, reason)
+            }
+            PianoffError::InvalidIdentityReply { bytes, reason } => {
+                write!(f, "Invalid Identity Reply ({}): {}", reason, bytes)
+            }
+            PianoffError::NoReply { timeout } => write!(
+                f,
+                "No Identity Reply within {} ms; the device may not support identity requests",
+                timeout.as_millis()
+            ),
+            PianoffError::NoInputPort { port, available } => write!(
+                f,
+                "No MIDI input port is named '{}' like the output port. Available input \
+                 ports: {}",
+                port,
+                quoted_list(available)
+            ),
+            PianoffError::Signal(reason) => {
+                write!(f, "Failed to install the signal handler: {}", reason)
+            }
+            PianoffError::Config { origin, reason } => {
+                write!(f, "Invalid configuration in {}: {}", origin, reason)
+            }
+            PianoffError::UnknownSetup { name, available } if available.is_empty() => write!(
+                f,
+                "No profile named '{}'; the configuration defines no profiles",
+                name
+            ),
+            PianoffError::UnknownSetup { name, available } => write!(
+                f,
+                "No profile named '{}' in the configuration; available: {}",
+                name,
+                quoted_list(available)
+            ),
+            PianoffError::MissingPort { command } => {
+                write!(f, "{} needs --port or a --profile with a port", command)
+            }
+            PianoffError::State { path, reason } => {
+                write!(f, "Invalid state file '{}': {}", path, reason)
+            }
+            PianoffError::Script {
+                origin,
+                line,
+                column,
+                reason,
+            } => write!(
+                f,
+                "Invalid script {} at line {}, column {}: {}",
+                origin, line, column, reason
+            ),
+            PianoffError::InvalidSysEx {
+                origin,
+                offset,
+                reason,
+            } => write!(
+                f,
+                "Invalid SysEx in {} at byte {}: {}",
+                origin, offset, reason
+            ),
+            PianoffError::Smf {
+                origin,
+                offset,
+                reason,
+            } => write!(
+                f,
+                "Invalid MIDI file {} at byte {}: {}",
+                origin, offset, reason
+            ),
+        }
+    }
+}
+
+impl Error for PianoffError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            PianoffError::Message(error) => Some(error),
+            PianoffError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<MidiMessageError> for PianoffError {
+    fn from(error: MidiMessageError) -> Self {
+        PianoffError::Message(error)
+    }
+}
+
+impl From<io::Error> for PianoffError {
+    fn from(error: io::Error) -> Self {
+        PianoffError::io("I/O error", error)
+    }
+}
+
+/// Which user input a validation warning refers to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputField {
+    Value,
+    Channel,
+}
+
+impl InputField {
+    /// Lower-case name used in messages
+    pub fn name(&self) -> &'static str {
+        match self {
+            InputField::Value => "value",
+            InputField::Channel => "channel",
+        }
+    }
+
+    /// Name used in error messages, e.g. "MIDI value"
+    pub fn label(&self) -> &'static str {
+        match self {
+            InputField::Value => "MIDI value",
+            InputField::Channel => "MIDI channel",
+        }
+    }
+
+    /// Largest accepted value, as sent (channels are 0-15 on the wire)
+    pub fn max(&self) -> u8 {
+        match self {
+            InputField::Value => 127,
+            InputField::Channel => 15,
+        }
+    }
+
+    /// Smallest and largest accepted input, in the current channel numbering
+    pub fn input_range(&self) -> (u8, u8) {
+        match self {
+            InputField::Value => (0, 127),
+            InputField::Channel => {
+                let numbering = crate::channel_numbering();
+                (numbering.first(), numbering.last())
+            }
+        }
+    }
+
+    /// Formats a value of this field the way the user enters it
+    fn display(&self, value: u8) -> u8 {
+        match self {
+            InputField::Value => value,
+            InputField::Channel => display_channel(value),
+        }
+    }
+}
+
+/// Why a validator fell back to its default instead of using the input
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ValidationWarning {
+    /// The input was a number, but outside the field's range
+    OutOfRange {
+        field: InputField,
+        value: u8,
+        default: u8,
+    },
+    /// The input was not a number at all
+    Invalid {
+        field: InputField,
+        input: String,
+        default: u8,
+    },
+    /// The input was accepted, but could easily mean something else
+    Ambiguous {
+        field: InputField,
+        input: String,
+        value: u8,
+        reason: String,
+    },
+}
+
+impl ValidationWarning {
+    /// The field the warning refers to
+    pub fn field(&self) -> InputField {
+        match self {
+            ValidationWarning::OutOfRange { field, .. }
+            | ValidationWarning::Invalid { field, .. }
+            | ValidationWarning::Ambiguous { field, .. } => *field,
+        }
+    }
+
+    /// The value that was used: the default, or the value read from an ambiguous input
+    pub fn default(&self) -> u8 {
+        match self {
+            ValidationWarning::OutOfRange { default, .. }
+            | ValidationWarning::Invalid { default, .. } => *default,
+            ValidationWarning::Ambiguous { value, .. } => *value,
+        }
+    }
+
+    /// Replaces the fallback value of an out-of-range or invalid warning
+    pub fn with_default(self, default: u8) -> Self {
+        match self {
+            ValidationWarning::OutOfRange { field, value, .. } => ValidationWarning::OutOfRange {
+                field,
+                value,
+                default,
+            },
+            ValidationWarning::Invalid { field, input, .. } => ValidationWarning::Invalid {
+                field,
+                input,
+                default,
+            },
+            ambiguous => ambiguous,
+        }
+    }
+
+    /// True when the input was rejected and the default was used instead
+    pub fn is_fallback(&self) -> bool {
+        !matches!(self, ValidationWarning::Ambiguous { .. })
+    }
+
+    /// The error strict validation reports for the same input
+    pub fn into_error(self) -> PianoffError {
+        match self {
+            ValidationWarning::OutOfRange { field, value, .. } => {
+                let (min, max) = field.input_range();
+                PianoffError::OutOfRange {
+                    field: field.label(),
+                    value: u32::from(value),
+                    min: u32::from(min),
+                    max: u32::from(max),
+                }
+            }
+            ValidationWarning::Invalid { field, input, .. }
+            | ValidationWarning::Ambiguous { field, input, .. } => PianoffError::Parse {
+                field: field.label(),
+                input,
+            },
+        }
+    }
+}
+
+impl fmt::Display for ValidationWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValidationWarning::OutOfRange {
+                field,
+                value,
+                default,
+            } => {
+                let label = match field {
+                    InputField::Value => "Value",
+                    InputField::Channel => "Channel",
+                };
+                let (min, max) = field.input_range();
+                write!(
+                    f,
+                    "Warning: {} {} is out of range ({}-{}). Using default {} {}.",
+                    label,
+                    value,
+                    min,
+                    max,
+                    field.name(),
+                    field.display(*default)
+                )
+            }
+            ValidationWarning::Invalid {
+                field,
+                input,
+                default,
+            } => write!(
+                f,
+                "Warning: Invalid {} '{}'. Using default {} {}.",
+                field.name(),
+                input,
+                field.name(),
+                field.display(*default)
+            ),
+            ValidationWarning::Ambiguous {
+                field,
+                input,
+                value,
+                reason,
+            } => write!(
+                f,
+                "Warning: '{}' is ambiguous; using {} {} ({}).",
+                input,
+                field.name(),
+                field.display(*value),
+                reason
+            ),
+        }
+    }
+}
+
+impl Error for ValidationWarning {}
+
+fn quoted_list(names: &[String]) -> String {
+    names
+        .iter()
+        .map(|name| format!("'{}'", name))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
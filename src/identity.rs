@@ -0,0 +1,209 @@
+use crate::{MidiMessage, MidiSink, PianoffError, format_hex};
+use std::fmt;
+use std::sync::mpsc::Receiver;
+use std::time::{Duration, Instant};
+
+/// Universal Non-Realtime Identity Request, addressed to every device
+pub const IDENTITY_REQUEST: [u8; 6] = [0xF0, 0x7E, 0x7F, 0x06, 0x01, 0xF7];
+
+/// SysEx manufacturer IDs of common keyboard makers
+pub const KNOWN_MANUFACTURERS: &[(&[u8], &str)] = &[
+    (&[0x01], "Sequential"),
+    (&[0x07], "Kurzweil"),
+    (&[0x0F], "Ensoniq"),
+    (&[0x40], "Kawai"),
+    (&[0x41], "Roland"),
+    (&[0x42], "Korg"),
+    (&[0x43], "Yamaha"),
+    (&[0x44], "Casio"),
+    (&[0x47], "Akai"),
+    (&[0x00, 0x00, 0x0E], "Alesis"),
+    (&[0x00, 0x20, 0x29], "Novation"),
+    (&[0x00, 0x20, 0x33], "Clavia (Nord)"),
+    (&[0x00, 0x20, 0x6B], "Arturia"),
+];
+
+/// Returns the name of a manufacturer ID from `KNOWN_MANUFACTURERS`
+pub fn manufacturer_name(manufacturer: &[u8]) -> Option<&'static str> {
+    KNOWN_MANUFACTURERS
+        .iter()
+        .find(|(id, _)| *id == manufacturer)
+        .map(|(_, name)| *name)
+}
+
+/// Identity of a device, as reported in a SysEx Identity Reply
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceIdentity {
+    /// Manufacturer ID: one byte, or three bytes starting with 00
+    pub manufacturer: Vec<u8>,
+    /// Family code; written in hex MSB first, so 0x0219 is sent as 19 02
+    pub family: u16,
+    /// Model number, encoded like the family code
+    pub model: u16,
+    /// Software revision level
+    pub version: [u8; 4],
+}
+
+impl DeviceIdentity {
+    /// Parses an Identity Reply: `F0 7E <device> 06 02 <manufacturer> <family> <model> <version> F7`
+    /// Bytes a device appends after the version are ignored
+    pub fn from_reply(bytes: &[u8]) -> Result<Self, PianoffError> {
+        let invalid = |reason: &'static str| PianoffError::InvalidIdentityReply {
+            bytes: format_hex(bytes),
+            reason,
+        };
+
+        let payload = match MidiMessage::from_bytes(bytes)? {
+            MidiMessage::SysEx(payload) => payload,
+            _ => return Err(invalid("not a SysEx message")),
+        };
+        let body = match payload.as_slice() {
+            [0x7E, _, 0x06, 0x02, body @ ..] => body,
+            _ => return Err(invalid("not an Identity Reply")),
+        };
+
+        let id_length = if body.first() == Some(&0x00) { 3 } else { 1 };
+        if body.len() < id_length + 8 {
+            return Err(invalid("reply is too short"));
+        }
+        let (manufacturer, rest) = body.split_at(id_length);
+
+        Ok(DeviceIdentity {
+            manufacturer: manufacturer.to_vec(),
+            family: u16::from_le_bytes([rest[0], rest[1]]),
+            model: u16::from_le_bytes([rest[2], rest[3]]),
+            version: [rest[4], rest[5], rest[6], rest[7]],
+        })
+    }
+
+    /// Name of the manufacturer, if it is a well-known one
+    pub fn manufacturer_name(&self) -> Option<&'static str> {
+        manufacturer_name(&self.manufacturer)
+    }
+}
+
+impl fmt::Display for DeviceIdentity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.manufacturer_name() {
+            Some(name) => write!(f, "{} ({})", name, format_hex(&self.manufacturer))?,
+            None => write!(f, "Manufacturer {}", format_hex(&self.manufacturer))?,
+        }
+        write!(
+            f,
+            ", family 0x{:04X}, model 0x{:04X}, version {}",
+            self.family,
+            self.model,
+            format_hex(&self.version)
+        )
+    }
+}
+
+/// Sends an Identity Request and waits for the reply on `replies`
+/// Other incoming messages are skipped; fails with `PianoffError::NoReply` after `timeout`
+pub fn request_identity<S: MidiSink + ?Sized>(
+    sink: &mut S,
+    replies: &Receiver<Vec<u8>>,
+    timeout: Duration,
+) -> Result<DeviceIdentity, PianoffError> {
+    sink.send(&IDENTITY_REQUEST)?;
+
+    let deadline = Instant::now() + timeout;
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        match replies.recv_timeout(remaining) {
+            Ok(message) => {
+                if let Ok(identity) = DeviceIdentity::from_reply(&message) {
+                    return Ok(identity);
+                }
+            }
+            Err(_) => return Err(PianoffError::NoReply { timeout }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RecordingSink;
+    use std::sync::mpsc;
+
+    const YAMAHA_REPLY: [u8; 15] = [
+        0xF0, 0x7E, 0x10, 0x06, 0x02, 0x43, 0x00, 0x41, 0x52, 0x06, 0x00, 0x00, 0x00, 0x7F, 0xF7,
+    ];
+
+    #[test]
+    fn test_parse_identity_reply() {
+        let identity = DeviceIdentity::from_reply(&YAMAHA_REPLY).unwrap();
+        assert_eq!(
+            identity,
+            DeviceIdentity {
+                manufacturer: vec![0x43],
+                family: 0x4100,
+                model: 0x0652,
+                version: [0x00, 0x00, 0x00, 0x7F],
+            }
+        );
+        assert_eq!(
+            identity.to_string(),
+            "Yamaha (43), family 0x4100, model 0x0652, version 00 00 00 7F"
+        );
+
+        let reply = [
+            0xF0, 0x7E, 0x7F, 0x06, 0x02, 0x00, 0x20, 0x6B, 0x02, 0x00, 0x04, 0x01, 0x01, 0x02,
+            0x03, 0x04, 0xF7,
+        ];
+        let identity = DeviceIdentity::from_reply(&reply).unwrap();
+        assert_eq!(identity.manufacturer, vec![0x00, 0x20, 0x6B]);
+        assert_eq!(identity.manufacturer_name(), Some("Arturia"));
+        assert_eq!((identity.family, identity.model), (0x0002, 0x0104));
+    }
+
+    #[test]
+    fn test_reject_non_identity_replies() {
+        let cases: [&[u8]; 5] = [
+            &IDENTITY_REQUEST,
+            &[0xB0, 0x7A, 0x00],
+            &[0xF0, 0x7E, 0x10, 0x06, 0x02, 0x43, 0x00, 0x41, 0xF7],
+            &[
+                0xF0, 0x43, 0x10, 0x06, 0x02, 0x43, 0x00, 0x41, 0x52, 0x06, 0x00, 0x00, 0x00, 0x7F,
+                0xF7,
+            ],
+            &[0xF0, 0x7E, 0x10, 0x06, 0x02, 0x43],
+        ];
+
+        for bytes in cases {
+            assert!(
+                DeviceIdentity::from_reply(bytes).is_err(),
+                "Accepted {}",
+                format_hex(bytes)
+            );
+        }
+    }
+
+    #[test]
+    fn test_request_identity_skips_other_messages() {
+        let (sender, receiver) = mpsc::channel();
+        sender.send(vec![0xF8]).unwrap();
+        sender.send(vec![0x90, 60, 100]).unwrap();
+        sender.send(YAMAHA_REPLY.to_vec()).unwrap();
+
+        let mut sink = RecordingSink::new();
+        let identity = request_identity(&mut sink, &receiver, Duration::from_millis(100)).unwrap();
+
+        assert_eq!(sink.messages, vec![IDENTITY_REQUEST.to_vec()]);
+        assert_eq!(identity.manufacturer_name(), Some("Yamaha"));
+    }
+
+    #[test]
+    fn test_request_identity_times_out() {
+        let (_sender, receiver) = mpsc::channel::<Vec<u8>>();
+        let mut sink = RecordingSink::new();
+
+        let error = request_identity(&mut sink, &receiver, Duration::from_millis(10)).unwrap_err();
+        assert!(matches!(error, PianoffError::NoReply { .. }));
+        assert_eq!(
+            error.to_string(),
+            "No Identity Reply within 10 ms; the device may not support identity requests"
+        );
+    }
+}
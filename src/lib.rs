@@ -1,44 +1,437 @@
-use std::error::Error;
+pub mod channel_mode;
+pub mod config;
+pub mod daemon;
+pub mod error;
+pub mod identity;
+pub mod message;
+pub mod monitor;
+pub mod profile;
+pub mod route;
+pub mod script;
+pub mod smf;
+pub mod state;
+pub mod sysex;
 
-/// Validates MIDI value input (0-127)
-/// Returns validated value or default (0) with warning message
-pub fn validate_midi_value(input: &str) -> (u8, Option<String>) {
-    if input.trim().is_empty() {
-        return (0, None);
+pub use channel_mode::{
+    ChannelMode, create_channel_mode_message, interpret_channel_mode_value, send_channel_mode,
+};
+pub use config::{Config, Setup};
+pub use daemon::{PortEvent, PortWatcher, WatchedPort, format_log_line};
+pub use error::{InputField, PianoffError, ValidationWarning};
+pub use identity::{
+    DeviceIdentity, IDENTITY_REQUEST, KNOWN_MANUFACTURERS, manufacturer_name, request_identity,
+};
+pub use message::{MidiMessage, MidiMessageError, message_length};
+pub use monitor::{
+    MESSAGE_TYPE_NAMES, MessageType, MonitorFilter, describe_message, format_monitor_line,
+    note_name,
+};
+pub use profile::{
+    DeviceProfile, IdentityPattern, LocalControlMethod, ProfileDb, send_local_control,
+};
+pub use route::{ROUTE_POLL_INTERVAL, route_messages};
+pub use script::{Script, ScriptLine, ScriptStep};
+pub use smf::{
+    DEFAULT_PPQ, DEFAULT_TEMPO, Division, Smf, SmfEvent, SmfRecorder, SmfWriter, TrackEvent,
+    play_messages, record_messages, write_vlq,
+};
+pub use state::{LastUsed, State};
+pub use sysex::{SysEx, send_sysex};
+
+use midir::MidiOutputConnection;
+use regex::Regex;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+
+/// How validators treat input they cannot use
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ValidationMode {
+    /// Fall back to the default and report a warning
+    #[default]
+    Lenient,
+    /// Reject the input with an error
+    Strict,
+}
+
+/// How channels are numbered in user input and output
+/// Messages always carry channels 0-15; only the numbers people type and read change
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ChannelNumbering {
+    /// Channels 0-15, as encoded in the status byte
+    #[default]
+    ZeroBased,
+    /// Channels 1-16, as printed on most instruments and in their manuals
+    OneBased,
+}
+
+impl ChannelNumbering {
+    /// Number shown for the first channel
+    pub fn first(self) -> u8 {
+        match self {
+            ChannelNumbering::ZeroBased => 0,
+            ChannelNumbering::OneBased => 1,
+        }
     }
-    
-    match input.trim().parse::<u8>() {
-        Ok(val) if val <= 127 => (val, None),
-        Ok(val) => (0, Some(format!("Warning: Value {} is out of range (0-127). Using default value 0.", val))),
-        Err(_) => (0, Some(format!("Warning: Invalid value '{}'. Using default value 0.", input.trim()))),
+
+    /// Number shown for the last channel
+    pub fn last(self) -> u8 {
+        self.first() + 15
+    }
+
+    /// Converts a wire channel (0-15) to the number shown to the user
+    pub fn display(self, channel: u8) -> u8 {
+        channel.saturating_add(self.first())
+    }
+
+    /// Converts a channel number given by the user to the wire channel (0-15)
+    pub fn from_display(self, number: u8) -> Result<u8, PianoffError> {
+        if number < self.first() || number > self.last() {
+            return Err(PianoffError::OutOfRange {
+                field: "MIDI channel",
+                value: u32::from(number),
+                min: u32::from(self.first()),
+                max: u32::from(self.last()),
+            });
+        }
+        Ok(number - self.first())
+    }
+}
+
+static ONE_BASED_CHANNELS: AtomicBool = AtomicBool::new(false);
+
+/// Selects the channel numbering used by validators, confirmations and error messages
+/// The setting is process-wide; the default is `ChannelNumbering::ZeroBased`
+pub fn set_channel_numbering(numbering: ChannelNumbering) {
+    ONE_BASED_CHANNELS.store(numbering == ChannelNumbering::OneBased, Ordering::Relaxed);
+}
+
+/// Returns the channel numbering selected with `set_channel_numbering`
+pub fn channel_numbering() -> ChannelNumbering {
+    if ONE_BASED_CHANNELS.load(Ordering::Relaxed) {
+        ChannelNumbering::OneBased
+    } else {
+        ChannelNumbering::ZeroBased
+    }
+}
+
+/// Converts a wire channel (0-15) to the number shown in the current numbering
+pub fn display_channel(channel: u8) -> u8 {
+    channel_numbering().display(channel)
+}
+
+/// Validates MIDI value input (0-127), written in any form `parse_midi_value` accepts
+/// Returns validated value or default (0) with a warning
+pub fn validate_midi_value(input: &str) -> (u8, Option<ValidationWarning>) {
+    validate_input(InputField::Value, input, 0)
+}
+
+/// Validates MIDI channel input (0-15, or 1-16 with one-based numbering)
+/// Returns validated wire channel or default (0) with a warning
+pub fn validate_midi_channel(input: &str) -> (u8, Option<ValidationWarning>) {
+    validate_input(InputField::Channel, input, 0)
+}
+
+/// Validates MIDI value input (0-127) with a caller-chosen default
+/// Empty input yields the default; in strict mode unusable input is an error
+pub fn validate_midi_value_with(
+    input: &str,
+    default: u8,
+    mode: ValidationMode,
+) -> Result<(u8, Option<ValidationWarning>), PianoffError> {
+    validate_input_with(InputField::Value, input, default, mode)
+}
+
+/// Validates MIDI channel input with a caller-chosen default wire channel (0-15)
+/// Empty input yields the default; in strict mode unusable input is an error
+pub fn validate_midi_channel_with(
+    input: &str,
+    default: u8,
+    mode: ValidationMode,
+) -> Result<(u8, Option<ValidationWarning>), PianoffError> {
+    validate_input_with(InputField::Channel, input, default, mode)
+}
+
+fn validate_input_with(
+    field: InputField,
+    input: &str,
+    default: u8,
+    mode: ValidationMode,
+) -> Result<(u8, Option<ValidationWarning>), PianoffError> {
+    check_range(field.label(), default, field.max())?;
+
+    match (validate_input(field, input, default), mode) {
+        ((_, Some(warning)), ValidationMode::Strict) if warning.is_fallback() => {
+            Err(warning.into_error())
+        }
+        (result, _) => Ok(result),
     }
 }
 
-/// Validates MIDI channel input (0-15)
-/// Returns validated channel or default (0) with warning message
-pub fn validate_midi_channel(input: &str) -> (u8, Option<String>) {
+fn validate_input(field: InputField, input: &str, default: u8) -> (u8, Option<ValidationWarning>) {
     if input.trim().is_empty() {
-        return (0, None);
+        return (default, None);
+    }
+
+    if field == InputField::Value {
+        return match parse_midi_value(input) {
+            Ok((value, ambiguity)) => (value, ambiguity),
+            Err(warning) => (default, Some(warning.with_default(default))),
+        };
     }
     
     match input.trim().parse::<u8>() {
-        Ok(ch) if ch <= 15 => (ch, None),
-        Ok(ch) => (0, Some(format!("Warning: Channel {} is out of range (0-15). Using default channel 0.", ch))),
-        Err(_) => (0, Some(format!("Warning: Invalid channel '{}'. Using default channel 0.", input.trim()))),
+        Ok(val) => match channel_numbering().from_display(val) {
+            Ok(channel) => (channel, None),
+            Err(_) => (
+                default,
+                Some(ValidationWarning::OutOfRange {
+                    field,
+                    value: val,
+                    default,
+                }),
+            ),
+        },
+        Err(_) => (
+            default,
+            Some(ValidationWarning::Invalid {
+                field,
+                input: input.trim().to_string(),
+                default,
+            }),
+        ),
     }
 }
 
-/// Creates MIDI Control Change message for controller #122
-/// Returns the 3-byte MIDI message array
-pub fn create_midi_cc_122_message(value: u8, channel: u8) -> Result<[u8; 3], Box<dyn Error>> {
-    if value > 127 {
-        return Err(format!("Invalid MIDI value: {}. Must be 0-127.", value).into());
+/// Parses a MIDI value (0-127) written in any of these forms, case-insensitively:
+///
+/// - decimal: `0` to `127`
+/// - hexadecimal: `0x00` to `0x7F`
+/// - percentage: `0%` to `100%`, fractions allowed, scaled to 0-127 and rounded
+/// - keywords: `on`, `true`, `yes`, `max` = 127; `off`, `false`, `no`, `min` = 0
+///
+/// Returns the value plus an `Ambiguous` warning for inputs that are easy to misread:
+/// a bare `1` (on/off intent) and decimals with leading zeros (octal intent).
+/// Unusable input is reported as an `OutOfRange` or `Invalid` warning with default 0.
+pub fn parse_midi_value(input: &str) -> Result<(u8, Option<ValidationWarning>), ValidationWarning> {
+    let trimmed = input.trim();
+    let lower = trimmed.to_lowercase();
+    let invalid = || ValidationWarning::Invalid {
+        field: InputField::Value,
+        input: trimmed.to_string(),
+        default: 0,
+    };
+    let check = |value: u8| {
+        if value > 127 {
+            Err(ValidationWarning::OutOfRange {
+                field: InputField::Value,
+                value,
+                default: 0,
+            })
+        } else {
+            Ok(value)
+        }
+    };
+    let ambiguous = |value: u8, reason: &str| ValidationWarning::Ambiguous {
+        field: InputField::Value,
+        input: trimmed.to_string(),
+        value,
+        reason: reason.to_string(),
+    };
+
+    match lower.as_str() {
+        "on" | "true" | "yes" | "max" => return Ok((127, None)),
+        "off" | "false" | "no" | "min" => return Ok((0, None)),
+        _ => {}
+    }
+
+    if let Some(hex) = lower.strip_prefix("0x") {
+        if hex.is_empty() || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(invalid());
+        }
+        let value = u8::from_str_radix(hex, 16).map_err(|_| invalid())?;
+        return check(value).map(|value| (value, None));
+    }
+
+    if let Some(percent) = lower.strip_suffix('%') {
+        let percent = percent.trim();
+        if percent.is_empty() || !percent.chars().all(|c| c.is_ascii_digit() || c == '.') {
+            return Err(invalid());
+        }
+        let percent: f64 = percent.parse().map_err(|_| invalid())?;
+        let value = (percent * 127.0 / 100.0).round() as u8;
+        if percent > 100.0 {
+            // A hair over 100% still rounds to 127, so report at least 128
+            return Err(ValidationWarning::OutOfRange {
+                field: InputField::Value,
+                value: value.max(128),
+                default: 0,
+            });
+        }
+        return Ok((value, None));
+    }
+
+    let value = check(trimmed.parse::<u8>().map_err(|_| invalid())?)?;
+    if value == 1 {
+        return Ok((1, Some(ambiguous(1, "use 'on' or 127 for full on"))));
     }
+    if trimmed.len() > 1 && trimmed.starts_with('0') {
+        return Ok((
+            value,
+            Some(ambiguous(
+                value,
+                "leading zeros are read as decimal, not octal",
+            )),
+        ));
+    }
+
+    Ok((value, None))
+}
+
+/// True for controllers that work as on/off switches: the pedals and Legato through
+/// Hold 2 (64-69), and Local Control (122)
+pub fn is_switch_controller(controller: u8) -> bool {
+    matches!(controller, 64..=69 | 122)
+}
+
+/// Keeps a `parse_midi_value` warning only if it applies to a value for `controller`:
+/// a bare `1` may mean "on" for a switch, but is just a small value for anything else
+pub fn controller_value_warning(
+    warning: Option<ValidationWarning>,
+    controller: u8,
+) -> Option<ValidationWarning> {
+    match warning {
+        Some(ValidationWarning::Ambiguous { value: 1, .. })
+            if !is_switch_controller(controller) =>
+        {
+            None
+        }
+        warning => warning,
+    }
+}
+
+/// Well-known Control Change numbers, by name
+/// Several names may map to the same controller; the first one is canonical
+pub const KNOWN_CONTROLLERS: &[(&str, u8)] = &[
+    ("bank-select", 0),
+    ("modulation", 1),
+    ("breath", 2),
+    ("foot", 4),
+    ("portamento-time", 5),
+    ("data-entry", 6),
+    ("volume", 7),
+    ("balance", 8),
+    ("pan", 10),
+    ("expression", 11),
+    ("bank-select-lsb", 32),
+    ("sustain", 64),
+    ("damper", 64),
+    ("portamento", 65),
+    ("sostenuto", 66),
+    ("soft-pedal", 67),
+    ("soft", 67),
+    ("legato", 68),
+    ("hold-2", 69),
+    ("resonance", 71),
+    ("release", 72),
+    ("attack", 73),
+    ("brightness", 74),
+    ("reverb", 91),
+    ("tremolo", 92),
+    ("chorus", 93),
+    ("celeste", 94),
+    ("phaser", 95),
+    ("all-sound-off", 120),
+    ("reset-all-controllers", 121),
+    ("local-control", 122),
+    ("all-notes-off", 123),
+    ("omni-off", 124),
+    ("omni-on", 125),
+    ("mono-on", 126),
+    ("poly-on", 127),
+];
+
+/// Returns the canonical name of a well-known controller
+pub fn controller_name(controller: u8) -> Option<&'static str> {
+    KNOWN_CONTROLLERS
+        .iter()
+        .find(|(_, number)| *number == controller)
+        .map(|(name, _)| *name)
+}
+
+/// Parses a controller given as a number (0-127) or a known name such as "sustain"
+/// Names are case-insensitive and accept spaces or underscores instead of dashes
+pub fn parse_controller(input: &str) -> Result<u8, PianoffError> {
+    let input = input.trim();
+
+    if let Ok(number) = input.parse::<u8>() {
+        check_range("controller number", number, 127)?;
+        return Ok(number);
+    }
+
+    let normalized = input.to_lowercase().replace([' ', '_'], "-");
+    KNOWN_CONTROLLERS
+        .iter()
+        .find(|(name, _)| *name == normalized)
+        .map(|(_, number)| *number)
+        .ok_or_else(|| PianoffError::UnknownController(input.to_string()))
+}
+
+/// Fails with `PianoffError::OutOfRange` when `value` is above `max`
+pub(crate) fn check_range(field: &'static str, value: u8, max: u8) -> Result<(), PianoffError> {
+    if value > max {
+        return Err(PianoffError::OutOfRange {
+            field,
+            value: u32::from(value),
+            min: 0,
+            max: u32::from(max),
+        });
+    }
+    Ok(())
+}
+
+/// Fails with `MidiMessageError::ChannelOutOfRange` when a wire channel is above 15
+/// The error keeps the wire channel; only its message uses the current numbering
+fn check_channel(channel: u8) -> Result<(), PianoffError> {
     if channel > 15 {
-        return Err(format!("Invalid MIDI channel: {}. Must be 0-15.", channel).into());
+        return Err(MidiMessageError::ChannelOutOfRange(channel).into());
     }
-    
-    Ok([0xB0 + channel, 122, value])
+    Ok(())
+}
+
+/// Creates a MIDI Control Change message for any controller
+/// Returns the 3-byte MIDI message array
+pub fn create_cc_message(controller: u8, value: u8, channel: u8) -> Result<[u8; 3], PianoffError> {
+    check_range("controller number", controller, 127)?;
+    check_range("MIDI value", value, 127)?;
+    check_channel(channel)?;
+
+    Ok([0xB0 + channel, controller, value])
+}
+
+/// Creates MIDI Control Change message for controller #122
+/// Returns the 3-byte MIDI message array
+pub fn create_midi_cc_122_message(value: u8, channel: u8) -> Result<[u8; 3], PianoffError> {
+    create_cc_message(122, value, channel)
+}
+
+/// Every MIDI channel, for broadcasting a message to a device whose receive channel is unknown
+pub const ALL_CHANNELS: std::ops::RangeInclusive<u8> = 0..=15;
+
+/// Creates one CC #122 message per channel, in the given order
+/// Fails without building anything if the value or any channel is invalid
+pub fn create_midi_cc_122_batch<I: IntoIterator<Item = u8>>(
+    value: u8,
+    channels: I,
+) -> Result<Vec<[u8; 3]>, PianoffError> {
+    channels
+        .into_iter()
+        .map(|channel| create_midi_cc_122_message(value, channel))
+        .collect()
 }
 
 /// Interprets MIDI value for Local Control
@@ -50,6 +443,471 @@ pub fn interpret_local_control_value(value: u8) -> &'static str {
     }
 }
 
+/// The Local Control value that switches the other way: Off for On and vice versa
+/// Custom values count as On from 64 up, like switch controllers
+pub fn toggled_local_control_value(value: u8) -> u8 {
+    if value >= 64 { 0 } else { 127 }
+}
+
+/// Destination for raw MIDI messages
+/// Implemented for midir connections and for in-memory and hex-dump sinks
+pub trait MidiSink {
+    /// Sends one complete MIDI message
+    fn send(&mut self, message: &[u8]) -> Result<(), PianoffError>;
+}
+
+impl MidiSink for MidiOutputConnection {
+    fn send(&mut self, message: &[u8]) -> Result<(), PianoffError> {
+        MidiOutputConnection::send(self, message).map_err(|e| PianoffError::Send(e.to_string()))
+    }
+}
+
+impl<S: MidiSink + ?Sized> MidiSink for &mut S {
+    fn send(&mut self, message: &[u8]) -> Result<(), PianoffError> {
+        (**self).send(message)
+    }
+}
+
+impl<S: MidiSink + ?Sized> MidiSink for Box<S> {
+    fn send(&mut self, message: &[u8]) -> Result<(), PianoffError> {
+        (**self).send(message)
+    }
+}
+
+/// Sink that keeps every message in memory, mainly for tests
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct RecordingSink {
+    pub messages: Vec<Vec<u8>>,
+}
+
+impl RecordingSink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl MidiSink for RecordingSink {
+    fn send(&mut self, message: &[u8]) -> Result<(), PianoffError> {
+        self.messages.push(message.to_vec());
+        Ok(())
+    }
+}
+
+/// Sink that writes each message as a line of hex bytes (e.g. `B0 7A 00`)
+pub struct HexDumpSink<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> HexDumpSink<W> {
+    pub fn new(writer: W) -> Self {
+        HexDumpSink { writer }
+    }
+
+    /// Returns the underlying writer
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl HexDumpSink<File> {
+    /// Creates (or truncates) a hex-dump file
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<Self, PianoffError> {
+        let path = path.as_ref();
+        let file = File::create(path).map_err(|e| {
+            PianoffError::io(
+                format!("Failed to create dump file '{}'", path.display()),
+                e,
+            )
+        })?;
+        Ok(HexDumpSink::new(file))
+    }
+}
+
+impl HexDumpSink<io::Stdout> {
+    /// Hex-dump sink printing to standard output
+    pub fn stdout() -> Self {
+        HexDumpSink::new(io::stdout())
+    }
+}
+
+impl<W: Write> MidiSink for HexDumpSink<W> {
+    fn send(&mut self, message: &[u8]) -> Result<(), PianoffError> {
+        writeln!(self.writer, "{}", format_hex(message))
+            .and_then(|_| self.writer.flush())
+            .map_err(|e| PianoffError::io("Failed to write hex dump", e))
+    }
+}
+
+/// Formats bytes as space-separated uppercase hex
+pub fn format_hex(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|byte| format!("{:02X}", byte))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Parses hex bytes such as `F0 7E 7F 06 01 F7`, `f07e7f0601f7` or `0xF0, 0x7E`
+/// Bytes may be separated by spaces or commas, or written back to back in pairs
+pub fn parse_hex(input: &str) -> Result<Vec<u8>, PianoffError> {
+    let invalid = || PianoffError::Parse {
+        field: "hex bytes",
+        input: input.trim().to_string(),
+    };
+
+    let mut bytes = Vec::new();
+    for token in input.split(|c: char| c.is_whitespace() || c == ',') {
+        if token.is_empty() {
+            continue;
+        }
+        let token = token
+            .strip_prefix("0x")
+            .or_else(|| token.strip_prefix("0X"))
+            .unwrap_or(token);
+        if token.is_empty() || !token.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(invalid());
+        }
+        match token.len() {
+            1 | 2 => bytes.push(u8::from_str_radix(token, 16).map_err(|_| invalid())?),
+            len if len % 2 == 0 => {
+                for pair in token.as_bytes().chunks(2) {
+                    let pair = std::str::from_utf8(pair).map_err(|_| invalid())?;
+                    bytes.push(u8::from_str_radix(pair, 16).map_err(|_| invalid())?);
+                }
+            }
+            _ => return Err(invalid()),
+        }
+    }
+
+    if bytes.is_empty() {
+        return Err(invalid());
+    }
+    Ok(bytes)
+}
+
+/// Builds the confirmation line printed after CC #122 was sent
+pub fn format_cc_122_confirmation(value: u8, channel: u8) -> String {
+    format!(
+        "{} on channel {}",
+        cc_122_sent_prefix(value),
+        display_channel(channel)
+    )
+}
+
+fn cc_122_sent_prefix(value: u8) -> String {
+    let control_display = match value {
+        0 | 127 => interpret_local_control_value(value).to_string(),
+        _ => format!("Local Control Value {}", value),
+    };
+
+    format!(
+        "✓ Successfully sent MIDI CC #122: {} (value: {})",
+        control_display, value
+    )
+}
+
+/// Creates and sends MIDI Control Change message #122 (Local Control) to a sink
+/// Returns the confirmation text to display
+pub fn send_midi_cc_122<S: MidiSink + ?Sized>(
+    sink: &mut S,
+    value: u8,
+    channel: u8,
+) -> Result<String, PianoffError> {
+    let midi_message = create_midi_cc_122_message(value, channel)?;
+
+    sink.send(&midi_message)?;
+
+    Ok(format_cc_122_confirmation(value, channel))
+}
+
+/// Sends CC #122 on several channels, waiting `pacing` between messages
+/// Returns a summary listing every channel the message was sent on
+pub fn send_midi_cc_122_batch<S: MidiSink + ?Sized, I: IntoIterator<Item = u8>>(
+    sink: &mut S,
+    value: u8,
+    channels: I,
+    pacing: Duration,
+) -> Result<String, PianoffError> {
+    let channels: Vec<u8> = channels.into_iter().collect();
+    let messages = create_midi_cc_122_batch(value, channels.iter().copied())?;
+
+    for (i, message) in messages.iter().enumerate() {
+        if i > 0 && !pacing.is_zero() {
+            thread::sleep(pacing);
+        }
+        sink.send(message).map_err(|e| PianoffError::BatchSend {
+            channel: channels[i],
+            sent: channels[..i].to_vec(),
+            reason: match e {
+                PianoffError::Send(reason) => reason,
+                other => other.to_string(),
+            },
+        })?;
+    }
+
+    Ok(format!(
+        "{} on channels {}",
+        cc_122_sent_prefix(value),
+        format_channel_list(&channels)
+    ))
+}
+
+fn format_channel_list(channels: &[u8]) -> String {
+    channels
+        .iter()
+        .map(|channel| display_channel(*channel).to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Builds the confirmation line printed after a Control Change was sent
+pub fn format_cc_confirmation(controller: u8, value: u8, channel: u8) -> String {
+    match controller_name(controller) {
+        Some(name) => format!(
+            "✓ Successfully sent MIDI CC #{} ({}): value {} on channel {}",
+            controller,
+            name,
+            value,
+            display_channel(channel)
+        ),
+        None => format!(
+            "✓ Successfully sent MIDI CC #{}: value {} on channel {}",
+            controller,
+            value,
+            display_channel(channel)
+        ),
+    }
+}
+
+/// Creates and sends a Control Change message to a sink
+/// Returns the confirmation text to display
+pub fn send_cc<S: MidiSink + ?Sized>(
+    sink: &mut S,
+    controller: u8,
+    value: u8,
+    channel: u8,
+) -> Result<String, PianoffError> {
+    let midi_message = create_cc_message(controller, value, channel)?;
+
+    sink.send(&midi_message)?;
+
+    Ok(format_cc_confirmation(controller, value, channel))
+}
+
+/// Keeps a sink whose Local Control was switched off and switches it back on when
+/// dropped: on normal exit, early return or panic, and after SIGINT or SIGTERM when
+/// the program stops on `interrupt_flag` instead of being killed
+pub struct LocalControlGuard<S: MidiSink> {
+    sink: S,
+    channel: u8,
+    restore: Vec<u8>,
+    confirmation: String,
+    all_notes_off: bool,
+    armed: bool,
+}
+
+impl<S: MidiSink> LocalControlGuard<S> {
+    /// Guards `sink`, restoring Local Control with CC #122 = 127 on `channel`
+    pub fn new(sink: S, channel: u8) -> Result<Self, PianoffError> {
+        let restore = create_midi_cc_122_message(127, channel)?.to_vec();
+        Ok(LocalControlGuard {
+            sink,
+            channel,
+            restore,
+            confirmation: format_cc_122_confirmation(127, channel),
+            all_notes_off: false,
+            armed: true,
+        })
+    }
+
+    /// Restores with this message instead, for devices switched by SysEx
+    pub fn restore_with(mut self, message: Vec<u8>) -> Self {
+        self.confirmation = format!(
+            "✓ Successfully sent Local Control On via SysEx: {}",
+            format_hex(&message)
+        );
+        self.restore = message;
+        self
+    }
+
+    /// Also sends All Notes Off first, so no note keeps sounding
+    pub fn all_notes_off(mut self, enabled: bool) -> Self {
+        self.all_notes_off = enabled;
+        self
+    }
+
+    /// The guarded sink, for sending while the guard is held
+    pub fn sink(&mut self) -> &mut S {
+        &mut self.sink
+    }
+
+    /// Switches Local Control off through the guarded sink with `off`
+    /// When that fails the guard is disarmed, so a device that was never switched
+    /// off is not switched on, or sent All Notes Off, when the guard is dropped
+    pub fn switch_off<T>(
+        &mut self,
+        off: impl FnOnce(&mut S) -> Result<T, PianoffError>,
+    ) -> Result<T, PianoffError> {
+        self.armed = false;
+        let result = off(&mut self.sink);
+        self.armed = result.is_ok();
+        result
+    }
+
+    /// Restores Local Control now and returns the confirmation text
+    pub fn restore(mut self) -> Result<String, PianoffError> {
+        self.send_restore()
+    }
+
+    fn send_restore(&mut self) -> Result<String, PianoffError> {
+        self.armed = false;
+        if self.all_notes_off {
+            let message = create_channel_mode_message(ChannelMode::AllNotesOff, self.channel)?;
+            self.sink.send(&message)?;
+        }
+        self.sink.send(&self.restore)?;
+        Ok(self.confirmation.clone())
+    }
+}
+
+impl<S: MidiSink> Drop for LocalControlGuard<S> {
+    fn drop(&mut self) {
+        if self.armed {
+            // Nothing can report a failure here; the device may already be gone
+            let _ = self.send_restore();
+        }
+    }
+}
+
+/// Returns a flag that is set on SIGINT (Ctrl-C) or SIGTERM instead of the process
+/// being killed, so guards are dropped normally; can be installed once per process
+pub fn interrupt_flag() -> Result<Arc<AtomicBool>, PianoffError> {
+    let flag = Arc::new(AtomicBool::new(false));
+    let handler_flag = Arc::clone(&flag);
+    ctrlc::set_handler(move || handler_flag.store(true, Ordering::SeqCst))
+        .map_err(|e| PianoffError::Signal(e.to_string()))?;
+    Ok(flag)
+}
+
+/// How a port selector is matched against the available port names
+#[derive(Debug, Clone)]
+pub enum PortMatcher {
+    /// Position in the port list, as shown by `list-ports`
+    Index(usize),
+    /// Exact name, falling back to a case-insensitive substring
+    Name(String),
+    /// Regular expression, written as `/pattern/`
+    Regex(Regex),
+}
+
+impl PortMatcher {
+    /// Parses a selector: `/pattern/` is a regex, a plain number is an index,
+    /// anything else is matched by name
+    pub fn parse(selector: &str) -> Result<Self, PianoffError> {
+        let selector = selector.trim();
+        if selector.is_empty() {
+            return Err(PianoffError::InvalidPortSelector {
+                selector: String::new(),
+                reason: "Port selector must not be empty.".to_string(),
+            });
+        }
+
+        if let Some(pattern) = selector
+            .strip_prefix('/')
+            .and_then(|rest| rest.strip_suffix('/'))
+        {
+            let regex = Regex::new(pattern).map_err(|e| PianoffError::InvalidPortSelector {
+                selector: pattern.to_string(),
+                reason: e.to_string(),
+            })?;
+            return Ok(PortMatcher::Regex(regex));
+        }
+
+        match selector.parse::<usize>() {
+            Ok(index) => Ok(PortMatcher::Index(index)),
+            Err(_) => Ok(PortMatcher::Name(selector.to_string())),
+        }
+    }
+
+    /// True when a port name matches; index selectors never match by name
+    pub fn matches(&self, name: &str) -> bool {
+        match self {
+            PortMatcher::Index(_) => false,
+            PortMatcher::Name(selector) => {
+                name == selector || name.to_lowercase().contains(&selector.to_lowercase())
+            }
+            PortMatcher::Regex(regex) => regex.is_match(name),
+        }
+    }
+
+    /// Returns the index of the single port that matches
+    /// Fails when no port or more than one port matches
+    pub fn find<S: AsRef<str>>(&self, names: &[S]) -> Result<usize, PianoffError> {
+        if names.is_empty() {
+            return Err(PianoffError::NoPorts);
+        }
+
+        let candidates: Vec<usize> = match self {
+            PortMatcher::Index(index) => {
+                if *index >= names.len() {
+                    return Err(PianoffError::PortIndexOutOfRange {
+                        index: *index,
+                        count: names.len(),
+                    });
+                }
+                return Ok(*index);
+            }
+            PortMatcher::Name(name) => {
+                if let Some(index) = names.iter().position(|n| n.as_ref() == name) {
+                    return Ok(index);
+                }
+                let needle = name.to_lowercase();
+                matching_indices(names, |n| n.to_lowercase().contains(&needle))
+            }
+            PortMatcher::Regex(regex) => matching_indices(names, |n| regex.is_match(n)),
+        };
+
+        match candidates.as_slice() {
+            [index] => Ok(*index),
+            [] => Err(PianoffError::PortNotFound {
+                selector: self.to_string(),
+                available: names.iter().map(|n| n.as_ref().to_string()).collect(),
+            }),
+            _ => Err(PianoffError::AmbiguousPort {
+                selector: self.to_string(),
+                matches: candidates
+                    .iter()
+                    .map(|&i| names[i].as_ref().to_string())
+                    .collect(),
+            }),
+        }
+    }
+}
+
+impl std::fmt::Display for PortMatcher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PortMatcher::Index(index) => write!(f, "{}", index),
+            PortMatcher::Name(name) => write!(f, "{}", name),
+            PortMatcher::Regex(regex) => write!(f, "/{}/", regex.as_str()),
+        }
+    }
+}
+
+/// Finds the port matching a selector string (index, name, substring or `/regex/`)
+pub fn find_port<S: AsRef<str>>(names: &[S], selector: &str) -> Result<usize, PianoffError> {
+    PortMatcher::parse(selector)?.find(names)
+}
+
+fn matching_indices<S: AsRef<str>>(names: &[S], predicate: impl Fn(&str) -> bool) -> Vec<usize> {
+    names
+        .iter()
+        .enumerate()
+        .filter(|(_, name)| predicate(name.as_ref()))
+        .map(|(i, _)| i)
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -76,7 +934,7 @@ mod tests {
         let (value, warning) = validate_midi_value("128");
         assert_eq!(value, 0);
         assert!(warning.is_some());
-        assert!(warning.unwrap().contains("out of range"));
+        assert!(warning.unwrap().to_string().contains("out of range"));
 
         let (value, warning) = validate_midi_value("255");
         assert_eq!(value, 0);
@@ -89,7 +947,7 @@ mod tests {
         let (value, warning) = validate_midi_value("abc");
         assert_eq!(value, 0);
         assert!(warning.is_some());
-        assert!(warning.unwrap().contains("Invalid value"));
+        assert!(warning.unwrap().to_string().contains("Invalid value"));
 
         let (value, warning) = validate_midi_value("12.5");
         assert_eq!(value, 0);
@@ -118,7 +976,7 @@ mod tests {
         let (channel, warning) = validate_midi_channel("16");
         assert_eq!(channel, 0);
         assert!(warning.is_some());
-        assert!(warning.unwrap().contains("out of range"));
+        assert!(warning.unwrap().to_string().contains("out of range"));
 
         let (channel, warning) = validate_midi_channel("255");
         assert_eq!(channel, 0);
@@ -131,7 +989,7 @@ mod tests {
         let (channel, warning) = validate_midi_channel("xyz");
         assert_eq!(channel, 0);
         assert!(warning.is_some());
-        assert!(warning.unwrap().contains("Invalid channel"));
+        assert!(warning.unwrap().to_string().contains("Invalid channel"));
 
         let (channel, warning) = validate_midi_channel("5.5");
         assert_eq!(channel, 0);
@@ -221,7 +1079,7 @@ mod tests {
             match expected_warning_contains {
                 Some(expected_text) => {
                     assert!(warning.is_some(), "Expected warning for input: '{}'", input);
-                    assert!(warning.unwrap().contains(expected_text), 
+                    assert!(warning.unwrap().to_string().contains(expected_text), 
                            "Warning doesn't contain expected text for input: '{}'", input);
                 }
                 None => {
@@ -251,7 +1109,7 @@ mod tests {
             match expected_warning_contains {
                 Some(expected_text) => {
                     assert!(warning.is_some(), "Expected warning for input: '{}'", input);
-                    assert!(warning.unwrap().contains(expected_text), 
+                    assert!(warning.unwrap().to_string().contains(expected_text), 
                            "Warning doesn't contain expected text for input: '{}'", input);
                 }
                 None => {
@@ -260,4 +1118,627 @@ mod tests {
             }
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_channel_numbering_conversions() {
+        let zero = ChannelNumbering::ZeroBased;
+        let one = ChannelNumbering::OneBased;
+
+        assert_eq!((zero.first(), zero.last()), (0, 15));
+        assert_eq!((one.first(), one.last()), (1, 16));
+        assert_eq!(zero.display(15), 15);
+        assert_eq!(one.display(15), 16);
+
+        assert_eq!(zero.from_display(0).unwrap(), 0);
+        assert_eq!(one.from_display(1).unwrap(), 0);
+        assert_eq!(one.from_display(16).unwrap(), 15);
+        assert_eq!(
+            one.from_display(0).unwrap_err().to_string(),
+            "Invalid MIDI channel: 0. Must be 1-16."
+        );
+        assert!(zero.from_display(16).is_err());
+    }
+
+    #[test]
+    fn test_find_port_by_index_and_exact_name() {
+        let ports = ["Midi Through Port-0", "P-125 MIDI 1", "USB Keystation"];
+        assert_eq!(find_port(&ports, "1").unwrap(), 1);
+        assert_eq!(find_port(&ports, "USB Keystation").unwrap(), 2);
+        assert!(find_port(&ports, "3").unwrap_err().to_string().contains("does not exist"));
+    }
+
+    #[test]
+    fn test_find_port_by_case_insensitive_substring() {
+        let ports = ["Midi Through Port-0", "Digital Piano:Digital Piano MIDI 1 24:0"];
+        assert_eq!(find_port(&ports, "digital piano").unwrap(), 1);
+        assert_eq!(find_port(&ports, "THROUGH").unwrap(), 0);
+    }
+
+    #[test]
+    fn test_find_port_exact_name_beats_substring() {
+        let ports = ["P-125 MIDI 1", "P-125"];
+        assert_eq!(find_port(&ports, "P-125").unwrap(), 1);
+    }
+
+    #[test]
+    fn test_find_port_by_regex() {
+        let ports = ["Midi Through Port-0", "P-125 MIDI 1", "P-45 MIDI 1"];
+        assert_eq!(find_port(&ports, "/^P-1\\d+/").unwrap(), 1);
+        assert!(find_port(&ports, "/[/").unwrap_err().to_string().contains("Invalid port pattern"));
+    }
+
+    #[test]
+    fn test_find_port_reports_zero_and_multiple_matches() {
+        let ports = ["P-125 MIDI 1", "P-45 MIDI 1"];
+
+        let error = find_port(&ports, "roland").unwrap_err().to_string();
+        assert!(error.contains("No MIDI port matches 'roland'"));
+        assert!(error.contains("'P-45 MIDI 1'"));
+
+        let error = find_port(&ports, "midi").unwrap_err().to_string();
+        assert!(error.contains("ambiguous"));
+        assert!(error.contains("'P-125 MIDI 1'") && error.contains("'P-45 MIDI 1'"));
+
+        let empty: [&str; 0] = [];
+        assert!(find_port(&empty, "piano").is_err());
+        assert!(find_port(&ports, "  ").is_err());
+    }
+
+    #[test]
+    fn test_send_midi_cc_122_records_message_and_confirms() {
+        let mut sink = RecordingSink::new();
+        let confirmation = send_midi_cc_122(&mut sink, 0, 3).unwrap();
+
+        assert_eq!(sink.messages, vec![vec![0xB3, 122, 0]]);
+        assert_eq!(
+            confirmation,
+            "✓ Successfully sent MIDI CC #122: Local Control Off (value: 0) on channel 3"
+        );
+    }
+
+    #[test]
+    fn test_send_midi_cc_122_rejects_invalid_input_without_sending() {
+        let mut sink = RecordingSink::new();
+        assert!(send_midi_cc_122(&mut sink, 128, 0).is_err());
+        assert!(send_midi_cc_122(&mut sink, 0, 16).is_err());
+        assert!(sink.messages.is_empty());
+    }
+
+    #[test]
+    fn test_format_cc_122_confirmation() {
+        assert!(format_cc_122_confirmation(127, 0).contains("Local Control On (value: 127)"));
+        assert!(format_cc_122_confirmation(64, 15).contains("Local Control Value 64 (value: 64) on channel 15"));
+    }
+
+    #[test]
+    fn test_parse_hex_forms() {
+        let expected = vec![0xF0, 0x7E, 0x7F, 0x06, 0x01, 0xF7];
+        assert_eq!(parse_hex("F0 7E 7F 06 01 F7").unwrap(), expected);
+        assert_eq!(parse_hex("f07e7f0601f7").unwrap(), expected);
+        assert_eq!(parse_hex("0xF0, 0x7E,0x7F 06 1 F7").unwrap(), expected);
+        assert_eq!(parse_hex(&format_hex(&expected)).unwrap(), expected);
+
+        for input in ["", "  ", "F0 GG", "F07", "0x", "F0 0x 7E F7", "F0-7E"] {
+            assert!(parse_hex(input).is_err(), "Accepted '{}'", input);
+        }
+    }
+
+    #[test]
+    fn test_hex_dump_sink_writes_one_line_per_message() {
+        let mut sink = HexDumpSink::new(Vec::new());
+        send_midi_cc_122(&mut sink, 127, 0).unwrap();
+        send_midi_cc_122(&mut sink, 0, 15).unwrap();
+
+        let dump = String::from_utf8(sink.into_inner()).unwrap();
+        assert_eq!(dump, "B0 7A 7F\nBF 7A 00\n");
+    }
+
+    #[test]
+    fn test_create_cc_message_valid() {
+        assert_eq!(create_cc_message(64, 127, 0).unwrap(), [0xB0, 64, 127]);
+        assert_eq!(create_cc_message(7, 100, 9).unwrap(), [0xB9, 7, 100]);
+        assert_eq!(create_cc_message(0, 0, 15).unwrap(), [0xBF, 0, 0]);
+    }
+
+    #[test]
+    fn test_create_cc_message_invalid() {
+        let result = create_cc_message(128, 0, 0);
+        assert!(result.unwrap_err().to_string().contains("Invalid controller number"));
+        assert!(create_cc_message(64, 128, 0).unwrap_err().to_string().contains("Invalid MIDI value"));
+        assert!(create_cc_message(64, 0, 16).unwrap_err().to_string().contains("Invalid MIDI channel"));
+    }
+
+    #[test]
+    fn test_cc_122_wraps_generic_builder() {
+        for channel in 0..=15 {
+            for value in [0, 64, 127] {
+                assert_eq!(
+                    create_midi_cc_122_message(value, channel).unwrap(),
+                    create_cc_message(122, value, channel).unwrap()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_controller_numbers_and_names() {
+        assert_eq!(parse_controller("64").unwrap(), 64);
+        assert_eq!(parse_controller("sustain").unwrap(), 64);
+        assert_eq!(parse_controller("Soft Pedal").unwrap(), 67);
+        assert_eq!(parse_controller("REVERB").unwrap(), 91);
+        assert_eq!(parse_controller("local_control").unwrap(), 122);
+        assert_eq!(parse_controller(" volume ").unwrap(), 7);
+
+        assert!(parse_controller("128").unwrap_err().to_string().contains("Invalid controller number"));
+        assert!(parse_controller("kazoo").unwrap_err().to_string().contains("Unknown controller"));
+    }
+
+    #[test]
+    fn test_controller_names_are_unique_and_valid() {
+        for (i, (name, number)) in KNOWN_CONTROLLERS.iter().enumerate() {
+            assert!(*number <= 127, "Controller {} out of range", name);
+            assert_eq!(parse_controller(name).unwrap(), *number);
+            assert!(
+                KNOWN_CONTROLLERS[i + 1..].iter().all(|(other, _)| other != name),
+                "Duplicate controller name: {}",
+                name
+            );
+        }
+        assert_eq!(controller_name(64), Some("sustain"));
+        assert_eq!(controller_name(3), None);
+    }
+
+    #[test]
+    fn test_send_cc_confirmation() {
+        let mut sink = RecordingSink::new();
+        let confirmation = send_cc(&mut sink, 64, 127, 2).unwrap();
+        assert_eq!(sink.messages, vec![vec![0xB2, 64, 127]]);
+        assert_eq!(confirmation, "✓ Successfully sent MIDI CC #64 (sustain): value 127 on channel 2");
+
+        let confirmation = send_cc(&mut sink, 3, 10, 0).unwrap();
+        assert_eq!(confirmation, "✓ Successfully sent MIDI CC #3: value 10 on channel 0");
+    }
+
+    /// Every message that can be built from the given 7-bit sample values
+    fn sample_messages() -> Vec<MidiMessage> {
+        let samples = [0u8, 1, 63, 64, 126, 127];
+        let wide = [0u16, 1, 127, 128, 8192, 16383];
+        let mut messages = Vec::new();
+
+        for channel in 0..=15 {
+            for &a in &samples {
+                messages.push(MidiMessage::ProgramChange { channel, program: a });
+                messages.push(MidiMessage::ChannelPressure { channel, pressure: a });
+                for &b in &samples {
+                    messages.push(MidiMessage::NoteOff { channel, note: a, velocity: b });
+                    messages.push(MidiMessage::NoteOn { channel, note: a, velocity: b });
+                    messages.push(MidiMessage::PolyAftertouch { channel, note: a, pressure: b });
+                    messages.push(MidiMessage::ControlChange { channel, controller: a, value: b });
+                }
+            }
+            for &value in &wide {
+                messages.push(MidiMessage::PitchBend { channel, value });
+            }
+        }
+        for &a in &samples {
+            messages.push(MidiMessage::MtcQuarterFrame(a));
+            messages.push(MidiMessage::SongSelect(a));
+        }
+        for &value in &wide {
+            messages.push(MidiMessage::SongPosition(value));
+        }
+        messages.extend([
+            MidiMessage::SysEx(vec![]),
+            MidiMessage::SysEx(vec![0x7E, 0x7F, 0x06, 0x01]),
+            MidiMessage::TuneRequest,
+            MidiMessage::TimingClock,
+            MidiMessage::Start,
+            MidiMessage::Continue,
+            MidiMessage::Stop,
+            MidiMessage::ActiveSensing,
+            MidiMessage::SystemReset,
+        ]);
+        messages
+    }
+
+    #[test]
+    fn test_midi_message_round_trip() {
+        // Property: from_bytes(to_bytes(m)) == m, and the length matches the status byte
+        for message in sample_messages() {
+            let bytes = message.to_bytes().unwrap();
+            assert_eq!(MidiMessage::from_bytes(&bytes).unwrap(), message, "Bytes: {:02X?}", bytes);
+            if let Some(length) = message_length(bytes[0]) {
+                assert_eq!(bytes.len(), length, "Length mismatch for {:?}", message);
+            }
+        }
+    }
+
+    #[test]
+    fn test_midi_message_byte_round_trip() {
+        // Property: every valid 1-3 byte sequence re-encodes to the same bytes
+        for status in 0x80..=0xFFu8 {
+            for data1 in [0u8, 0x22, 0x7F] {
+                for data2 in [0u8, 0x45, 0x7F] {
+                    let full = [status, data1, data2];
+                    for length in 1..=3 {
+                        if let Ok(message) = MidiMessage::from_bytes(&full[..length]) {
+                            assert_eq!(message.to_bytes().unwrap(), &full[..length]);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_midi_message_from_bytes_never_panics() {
+        // Property: arbitrary input yields Ok or a typed error, never a panic
+        let mut seed: u32 = 0x1234_5678;
+        for _ in 0..5000 {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            let length = (seed % 6) as usize;
+            let bytes: Vec<u8> = (0..length).map(|i| (seed >> (i * 5)) as u8).collect();
+            if let Ok(message) = MidiMessage::from_bytes(&bytes) {
+                assert_eq!(message.to_bytes().unwrap(), bytes);
+            }
+        }
+    }
+
+    #[test]
+    fn test_midi_message_cc_matches_legacy_builder() {
+        let message = MidiMessage::ControlChange { channel: 5, controller: 122, value: 127 };
+        assert_eq!(message.to_bytes().unwrap(), create_midi_cc_122_message(127, 5).unwrap());
+    }
+
+    #[test]
+    fn test_midi_message_from_bytes_errors() {
+        use MidiMessageError::*;
+
+        assert_eq!(MidiMessage::from_bytes(&[]), Err(Empty));
+        assert_eq!(MidiMessage::from_bytes(&[0x40, 0x00]), Err(MissingStatus(0x40)));
+        assert_eq!(MidiMessage::from_bytes(&[0xF4]), Err(UndefinedStatus(0xF4)));
+        assert_eq!(MidiMessage::from_bytes(&[0xFD]), Err(UndefinedStatus(0xFD)));
+        assert_eq!(MidiMessage::from_bytes(&[0xF7]), Err(UndefinedStatus(0xF7)));
+        assert_eq!(
+            MidiMessage::from_bytes(&[0x90, 60]),
+            Err(Truncated { status: 0x90, expected: 3, actual: 2 })
+        );
+        assert_eq!(
+            MidiMessage::from_bytes(&[0xC0, 1, 2]),
+            Err(TrailingBytes { status: 0xC0, expected: 2, actual: 3 })
+        );
+        assert_eq!(
+            MidiMessage::from_bytes(&[0xB0, 122, 0x80]),
+            Err(InvalidDataByte { position: 2, byte: 0x80 })
+        );
+        assert_eq!(MidiMessage::from_bytes(&[0xF0, 0x43, 0x10]), Err(UnterminatedSysEx));
+        assert_eq!(
+            MidiMessage::from_bytes(&[0xF0, 0x43, 0x90, 0xF7]),
+            Err(InvalidDataByte { position: 2, byte: 0x90 })
+        );
+        assert_eq!(
+            MidiMessage::from_bytes(&[0xF0, 0xF7, 0xF8]),
+            Err(TrailingBytes { status: 0xF0, expected: 2, actual: 3 })
+        );
+    }
+
+    #[test]
+    fn test_midi_message_to_bytes_validates_fields() {
+        use MidiMessageError::*;
+
+        let message = MidiMessage::NoteOn { channel: 16, note: 60, velocity: 100 };
+        assert_eq!(message.to_bytes(), Err(ChannelOutOfRange(16)));
+
+        let message = MidiMessage::ControlChange { channel: 0, controller: 122, value: 128 };
+        assert!(message.to_bytes().unwrap_err().to_string().contains("Invalid MIDI value: 128"));
+
+        let message = MidiMessage::PitchBend { channel: 0, value: 0x4000 };
+        assert_eq!(
+            message.to_bytes(),
+            Err(ValueOutOfRange { field: "pitch bend value", value: 0x4000, max: 0x3FFF })
+        );
+
+        let message = MidiMessage::SysEx(vec![0x43, 0xF7]);
+        assert_eq!(message.to_bytes(), Err(InvalidDataByte { position: 2, byte: 0xF7 }));
+    }
+
+    #[test]
+    fn test_pitch_bend_encoding() {
+        let centre = MidiMessage::PitchBend { channel: 0, value: 8192 };
+        assert_eq!(centre.to_bytes().unwrap(), vec![0xE0, 0x00, 0x40]);
+
+        let max = MidiMessage::PitchBend { channel: 3, value: 16383 };
+        assert_eq!(max.to_bytes().unwrap(), vec![0xE3, 0x7F, 0x7F]);
+    }
+
+    #[test]
+    fn test_create_midi_cc_122_batch_all_channels() {
+        let batch = create_midi_cc_122_batch(0, ALL_CHANNELS).unwrap();
+        assert_eq!(batch.len(), 16);
+        for (channel, message) in batch.iter().enumerate() {
+            assert_eq!(*message, [0xB0 + channel as u8, 122, 0]);
+        }
+
+        assert!(create_midi_cc_122_batch(0, [0, 16]).is_err());
+        assert!(create_midi_cc_122_batch(128, ALL_CHANNELS).is_err());
+        assert!(create_midi_cc_122_batch(0, []).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_send_midi_cc_122_batch_reports_every_channel() {
+        let mut sink = RecordingSink::new();
+        let summary = send_midi_cc_122_batch(&mut sink, 127, ALL_CHANNELS, Duration::ZERO).unwrap();
+
+        assert_eq!(sink.messages.len(), 16);
+        assert_eq!(sink.messages[15], vec![0xBF, 122, 127]);
+        assert_eq!(
+            summary,
+            "✓ Successfully sent MIDI CC #122: Local Control On (value: 127) on channels \
+             0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15"
+        );
+    }
+
+    #[test]
+    fn test_send_midi_cc_122_batch_validates_before_sending() {
+        let mut sink = RecordingSink::new();
+        assert!(send_midi_cc_122_batch(&mut sink, 0, [1, 2, 99], Duration::ZERO).is_err());
+        assert!(sink.messages.is_empty());
+    }
+
+    #[test]
+    fn test_send_midi_cc_122_batch_paces_messages() {
+        let mut sink = RecordingSink::new();
+        let start = std::time::Instant::now();
+        send_midi_cc_122_batch(&mut sink, 0, [0, 1, 2], Duration::from_millis(5)).unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(10));
+    }
+
+    #[test]
+    fn test_validation_warnings_are_structured() {
+        assert_eq!(
+            validate_midi_value("200").1,
+            Some(ValidationWarning::OutOfRange { field: InputField::Value, value: 200, default: 0 })
+        );
+        assert_eq!(
+            validate_midi_channel("ch1").1,
+            Some(ValidationWarning::Invalid {
+                field: InputField::Channel,
+                input: "ch1".to_string(),
+                default: 0,
+            })
+        );
+
+        let (_, warning) = validate_midi_channel("16");
+        let warning = warning.unwrap();
+        assert_eq!(warning.field(), InputField::Channel);
+        assert_eq!(warning.default(), 0);
+    }
+
+    #[test]
+    fn test_errors_are_typed() {
+        assert!(matches!(
+            create_cc_message(64, 128, 0),
+            Err(PianoffError::OutOfRange { field: "MIDI value", value: 128, min: 0, max: 127 })
+        ));
+        assert!(matches!(
+            create_midi_cc_122_message(0, 16),
+            Err(PianoffError::Message(MidiMessageError::ChannelOutOfRange(16)))
+        ));
+        assert!(matches!(parse_controller("kazoo"), Err(PianoffError::UnknownController(_))));
+
+        let empty: [&str; 0] = [];
+        assert!(matches!(find_port(&empty, "piano"), Err(PianoffError::NoPorts)));
+        assert!(matches!(
+            find_port(&["a", "b"], "5"),
+            Err(PianoffError::PortIndexOutOfRange { index: 5, count: 2 })
+        ));
+        assert!(matches!(find_port(&["a"], "/[/"), Err(PianoffError::InvalidPortSelector { .. })));
+        match find_port(&["P-125 MIDI 1", "P-45 MIDI 1"], "midi") {
+            Err(PianoffError::AmbiguousPort { selector, matches }) => {
+                assert_eq!(selector, "midi");
+                assert_eq!(matches, vec!["P-125 MIDI 1", "P-45 MIDI 1"]);
+            }
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_batch_send_error_lists_channels_already_sent() {
+        struct FailOn(u8);
+        impl MidiSink for FailOn {
+            fn send(&mut self, message: &[u8]) -> Result<(), PianoffError> {
+                if message[0] & 0x0F == self.0 {
+                    return Err(PianoffError::Send("buffer full".to_string()));
+                }
+                Ok(())
+            }
+        }
+
+        let error = send_midi_cc_122_batch(&mut FailOn(2), 0, ALL_CHANNELS, Duration::ZERO).unwrap_err();
+        match &error {
+            PianoffError::BatchSend { channel, sent, reason } => {
+                assert_eq!(*channel, 2);
+                assert_eq!(sent, &vec![0, 1]);
+                assert_eq!(reason, "buffer full");
+            }
+            other => panic!("Unexpected error: {:?}", other),
+        }
+        assert_eq!(
+            error.to_string(),
+            "Failed to send MIDI message on channel 2 (already sent on channels: 0, 1): buffer full"
+        );
+    }
+
+    #[test]
+    fn test_validate_with_custom_default() {
+        assert_eq!(validate_midi_value_with("", 127, ValidationMode::Lenient).unwrap(), (127, None));
+        assert_eq!(validate_midi_channel_with(" ", 9, ValidationMode::Strict).unwrap(), (9, None));
+
+        let (value, warning) = validate_midi_value_with("abc", 127, ValidationMode::Lenient).unwrap();
+        assert_eq!(value, 127);
+        assert_eq!(warning.unwrap().to_string(), "Warning: Invalid value 'abc'. Using default value 127.");
+
+        let (channel, warning) = validate_midi_channel_with("16", 3, ValidationMode::Lenient).unwrap();
+        assert_eq!(channel, 3);
+        assert!(warning.unwrap().to_string().contains("Using default channel 3"));
+    }
+
+    #[test]
+    fn test_validate_strict_mode_rejects_bad_input() {
+        assert_eq!(validate_midi_value_with("64", 0, ValidationMode::Strict).unwrap(), (64, None));
+
+        let error = validate_midi_value_with("128", 127, ValidationMode::Strict).unwrap_err();
+        assert!(matches!(error, PianoffError::OutOfRange { field: "MIDI value", value: 128, min: 0, max: 127 }));
+        assert_eq!(error.to_string(), "Invalid MIDI value: 128. Must be 0-127.");
+
+        let error = validate_midi_value_with("of", 127, ValidationMode::Strict).unwrap_err();
+        assert!(matches!(error, PianoffError::Parse { field: "MIDI value", .. }));
+
+        let error = validate_midi_channel_with("x", 0, ValidationMode::Strict).unwrap_err();
+        assert_eq!(error.to_string(), "Invalid MIDI channel 'x'");
+    }
+
+    #[test]
+    fn test_validate_rejects_invalid_default() {
+        assert!(validate_midi_value_with("", 128, ValidationMode::Lenient).is_err());
+        assert!(validate_midi_channel_with("", 16, ValidationMode::Lenient).is_err());
+    }
+
+    #[test]
+    fn test_parse_midi_value_grammar() {
+        assert_eq!(parse_midi_value("on").unwrap(), (127, None));
+        assert_eq!(parse_midi_value(" Yes ").unwrap(), (127, None));
+        assert_eq!(parse_midi_value("no").unwrap(), (0, None));
+        assert_eq!(parse_midi_value("min").unwrap(), (0, None));
+        assert_eq!(parse_midi_value("0X7f").unwrap(), (127, None));
+        assert_eq!(parse_midi_value("0x00").unwrap(), (0, None));
+        assert_eq!(parse_midi_value("25%").unwrap(), (32, None));
+        assert_eq!(parse_midi_value("12.5 %").unwrap(), (16, None));
+        assert_eq!(parse_midi_value("99").unwrap(), (99, None));
+    }
+
+    #[test]
+    fn test_parse_midi_value_rejections() {
+        assert!(matches!(parse_midi_value("0x80"), Err(ValidationWarning::OutOfRange { value: 128, .. })));
+        assert!(matches!(parse_midi_value("0x100"), Err(ValidationWarning::Invalid { .. })));
+        assert!(matches!(parse_midi_value("-5%"), Err(ValidationWarning::Invalid { .. })));
+        assert!(matches!(parse_midi_value("150%"), Err(ValidationWarning::OutOfRange { value: 191, .. })));
+        assert!(matches!(parse_midi_value("100.1%"), Err(ValidationWarning::OutOfRange { value: 128, .. })));
+        assert!(matches!(parse_midi_value("500%"), Err(ValidationWarning::OutOfRange { value: 255, .. })));
+        assert!(matches!(parse_midi_value("%"), Err(ValidationWarning::Invalid { .. })));
+        assert!(matches!(parse_midi_value("onn"), Err(ValidationWarning::Invalid { .. })));
+    }
+
+    #[test]
+    fn test_parse_midi_value_flags_ambiguous_input() {
+        let (value, warning) = parse_midi_value("1").unwrap();
+        assert_eq!(value, 1);
+        let warning = warning.unwrap();
+        assert!(!warning.is_fallback());
+        assert_eq!(warning.to_string(), "Warning: '1' is ambiguous; using value 1 (use 'on' or 127 for full on).");
+
+        let (value, warning) = parse_midi_value("010").unwrap();
+        assert_eq!(value, 10);
+        assert!(matches!(warning, Some(ValidationWarning::Ambiguous { value: 10, .. })));
+    }
+
+    #[test]
+    fn test_on_off_hint_only_for_switch_controllers() {
+        let (_, warning) = parse_midi_value("1").unwrap();
+        assert!(controller_value_warning(warning.clone(), 64).is_some());
+        assert!(controller_value_warning(warning.clone(), 122).is_some());
+        assert_eq!(controller_value_warning(warning, 7), None);
+
+        // Leading zeros are misleading whatever the controller
+        let (_, warning) = parse_midi_value("010").unwrap();
+        assert!(controller_value_warning(warning, 7).is_some());
+        assert!(!is_switch_controller(70));
+    }
+
+    #[test]
+    fn test_ambiguous_input_is_used_even_in_strict_mode() {
+        let (value, warning) = validate_midi_value_with("1", 127, ValidationMode::Strict).unwrap();
+        assert_eq!(value, 1);
+        assert!(warning.is_some());
+
+        let (value, warning) = validate_midi_value_with("0x90", 127, ValidationMode::Lenient).unwrap();
+        assert_eq!(value, 127);
+        assert_eq!(warning.unwrap().to_string(), "Warning: Value 144 is out of range (0-127). Using default value 127.");
+    }
+
+    #[test]
+    fn test_local_control_guard_restores_once() {
+        let mut sink = RecordingSink::new();
+        {
+            let mut guard = LocalControlGuard::new(&mut sink, 2).unwrap().all_notes_off(true);
+            send_midi_cc_122(guard.sink(), 0, 2).unwrap();
+            assert_eq!(
+                guard.restore().unwrap(),
+                "✓ Successfully sent MIDI CC #122: Local Control On (value: 127) on channel 2"
+            );
+        }
+        assert_eq!(
+            sink.messages,
+            vec![vec![0xB2, 122, 0], vec![0xB2, 123, 0], vec![0xB2, 122, 127]]
+        );
+
+        let mut sink = RecordingSink::new();
+        let guard = LocalControlGuard::new(&mut sink, 0).unwrap();
+        drop(guard.restore_with(vec![0xF0, 0x43, 0x7F, 0xF7]));
+        assert_eq!(sink.messages, vec![vec![0xF0, 0x43, 0x7F, 0xF7]]);
+
+        assert!(LocalControlGuard::new(RecordingSink::new(), 16).is_err());
+    }
+
+    #[test]
+    fn test_local_control_guard_arms_after_switching_off() {
+        let mut sink = RecordingSink::new();
+        {
+            let mut guard = LocalControlGuard::new(&mut sink, 1).unwrap().all_notes_off(true);
+            guard.switch_off(|sink| send_midi_cc_122(sink, 0, 1)).unwrap();
+        }
+        assert_eq!(
+            sink.messages,
+            vec![vec![0xB1, 122, 0], vec![0xB1, 123, 0], vec![0xB1, 122, 127]]
+        );
+
+        // A device that cannot be switched off must not be switched on either
+        let profile = DeviceProfile {
+            name: "Stage Piano".to_string(),
+            port: None,
+            identity: None,
+            channel: 1,
+            local_control: LocalControlMethod::Unsupported,
+            quirks: Vec::new(),
+        };
+        let mut sink = RecordingSink::new();
+        {
+            let mut guard = LocalControlGuard::new(&mut sink, 1).unwrap().all_notes_off(true);
+            let error = guard.switch_off(|sink| send_local_control(sink, &profile, 0, 1)).unwrap_err();
+            assert!(matches!(error, PianoffError::LocalControlUnsupported { .. }));
+        }
+        assert!(sink.messages.is_empty());
+    }
+
+    #[test]
+    fn test_local_control_guard_restores_on_panic() {
+        let mut sink = RecordingSink::new();
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let _guard = LocalControlGuard::new(&mut sink, 0).unwrap();
+            panic!("session crashed");
+        }));
+
+        assert!(result.is_err());
+        assert_eq!(sink.messages, vec![vec![0xB0, 122, 127]]);
+    }
+
+    #[test]
+    fn test_toggled_local_control_value() {
+        assert_eq!(toggled_local_control_value(0), 127);
+        assert_eq!(toggled_local_control_value(127), 0);
+        assert_eq!(toggled_local_control_value(63), 127);
+        assert_eq!(toggled_local_control_value(64), 0);
+    }
+}
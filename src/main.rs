@@ -1,93 +1,453 @@
+mod cli;
+mod ports;
+
+use clap::Parser;
+use cli::{Broadcast, Cli, Command, ConfigAction, LocalState, Target, ValueArg, WizardOptions};
 use midi_cc_sender::{
-    create_midi_cc_122_message, interpret_local_control_value, validate_midi_channel,
-    validate_midi_value,
+    ALL_CHANNELS, ChannelMode, Config, DEFAULT_PPQ, DEFAULT_TEMPO, DeviceIdentity, DeviceProfile,
+    HexDumpSink, IdentityPattern, KNOWN_CONTROLLERS, LastUsed, LocalControlGuard,
+    LocalControlMethod, MessageType, MidiMessage, MidiSink, MonitorFilter, PianoffError, PortEvent,
+    PortWatcher, ProfileDb, ROUTE_POLL_INTERVAL, Script, ScriptLine, ScriptStep, Setup, Smf,
+    SmfRecorder, SmfWriter, State, SysEx, WatchedPort, channel_numbering,
+    create_channel_mode_message, describe_message, display_channel, find_port, format_hex,
+    format_log_line, format_monitor_line, interpret_local_control_value, interrupt_flag, parse_hex,
+    play_messages, record_messages, route_messages, send_cc, send_channel_mode, send_local_control,
+    send_midi_cc_122, send_midi_cc_122_batch, send_sysex, set_channel_numbering,
+    toggled_local_control_value, validate_midi_channel_with, validate_midi_value_with,
+};
+use midir::{MidiInputConnection, MidiOutputConnection};
+use ports::{
+    TimedMessage, connect_input, connect_output, identify_device, listen, open_midi_input,
+    open_midi_output, port_names, resolve_port,
 };
-use midir::{MidiOutput, MidiOutputConnection};
-use std::error::Error;
+use std::env;
+use std::fs;
 use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc::Receiver;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+/// How long `--identify` waits for a device to answer
+const IDENTIFY_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Settings shared by every command, from global flags and profile files
+struct Settings {
+    profiles: ProfileDb,
+    /// Ask devices without a port profile for their identity
+    identify: bool,
+    /// Config file given with `--config`, read only when a setup is needed
+    config: Option<PathBuf>,
+    /// Setup selected with `--profile`
+    profile: Option<String>,
+    /// Where settings are remembered between runs, if anywhere
+    state_path: Option<PathBuf>,
+    /// Standard MIDI File to save one-shot commands' messages to
+    save_smf: Option<PathBuf>,
+}
+
+/// Prints a prompt and reads one line from stdin
+fn prompt(message: &str) -> Result<String, PianoffError> {
+    print!("{}", message);
+    io::stdout()
+        .flush()
+        .map_err(|e| PianoffError::io("Failed to write prompt", e))?;
+
+    let mut input = String::new();
+    io::stdin()
+        .read_line(&mut input)
+        .map_err(|e| PianoffError::io("Failed to read user input", e))?;
+    Ok(input)
+}
+
+/// Directory holding pianoff's configuration: `$XDG_CONFIG_HOME/pianoff` or `~/.config/pianoff`
+fn config_dir() -> Option<PathBuf> {
+    env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+        .map(|dir| dir.join("pianoff"))
+}
+
+/// Loads the built-in profiles, then the user's `profiles.toml` (or `.json`) and the
+/// `--profiles` file, each taking precedence over the ones before
+fn load_profiles(extra: Option<&Path>) -> Result<ProfileDb, PianoffError> {
+    let mut profiles = ProfileDb::builtin();
+
+    if let Some(dir) = config_dir() {
+        for name in ["profiles.toml", "profiles.json"] {
+            let path = dir.join(name);
+            if path.is_file() {
+                profiles.prepend(ProfileDb::load(&path)?);
+            }
+        }
+    }
+    if let Some(path) = extra {
+        profiles.prepend(ProfileDb::load(path)?);
+    }
+
+    Ok(profiles)
+}
+
+/// Remembers the Local Control value sent to a port on some channels, for `toggle`
+fn record_local_control(settings: &Settings, port_name: &str, channels: &[u8], value: u8) {
+    update_state(settings, |state| {
+        for &channel in channels {
+            state.record_local_control(port_name, channel, value);
+        }
+    });
+}
+
+/// The state file: `$XDG_STATE_HOME/pianoff/state.json`, or under `~/.local/state`
+fn state_path() -> Option<PathBuf> {
+    env::var_os("XDG_STATE_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".local/state")))
+        .map(|dir| dir.join("pianoff").join("state.json"))
+}
+
+/// Reads the remembered state; a broken state file is reported and ignored
+fn load_state(settings: &Settings) -> State {
+    let Some(path) = &settings.state_path else {
+        return State::default();
+    };
+    State::load(path).unwrap_or_else(|e| {
+        eprintln!("Note: {}", e);
+        State::default()
+    })
+}
+
+/// Updates the remembered state; failing to save it never fails the command
+fn update_state(settings: &Settings, update: impl FnOnce(&mut State)) {
+    let Some(path) = &settings.state_path else {
+        return;
+    };
+    let mut state = load_state(settings);
+    update(&mut state);
+    if let Err(e) = state.save(path) {
+        eprintln!("Note: {}", e);
+    }
+}
+
+/// Loads `--config`, which must exist, or else the user's `config.toml` if there is one
+/// Returns where the config was looked for along with its contents
+fn load_config(explicit: Option<&Path>) -> (Option<PathBuf>, Result<Config, PianoffError>) {
+    if let Some(path) = explicit {
+        return (Some(path.to_path_buf()), Config::load(path));
+    }
+
+    let path = config_dir().map(|dir| dir.join("config.toml"));
+    let config = match &path {
+        Some(path) if path.is_file() => Config::load(path),
+        _ => Ok(Config::default()),
+    };
+    (path, config)
+}
+
+/// The setup selected with `--profile`, or an empty one
+/// The config file is read only here, so a broken one only affects commands that
+/// need it; `--config` alone is still checked
+fn selected_setup(settings: &Settings) -> Result<Setup, PianoffError> {
+    if settings.profile.is_none() && settings.config.is_none() {
+        return Ok(Setup::default());
+    }
+    let (_, config) = load_config(settings.config.as_deref());
+    let config = config?;
+    match &settings.profile {
+        Some(name) => Ok(config.setup(name)?.clone()),
+        None => Ok(Setup::default()),
+    }
+}
+
+/// Fills the port, channel and Local Control value of a command from the selected
+/// setup, wherever they were not given on the command line
+fn apply_setup(command: &mut Command, settings: &Settings) -> Result<(), PianoffError> {
+    if matches!(
+        command,
+        Command::ListPorts
+            | Command::ListControllers
+            | Command::ListProfiles
+            | Command::Config { .. }
+    ) {
+        return Ok(());
+    }
+
+    let setup = selected_setup(settings)?;
+    let channel = setup.channel.map(display_channel);
+    let fill_target = |target: &mut Target| {
+        if target.dump.is_none() && target.port.is_none() {
+            target.port = setup.port.clone();
+        }
+        if target.channel.is_none() {
+            target.channel = channel;
+        }
+    };
+
+    match command {
+        Command::Off { target, .. }
+        | Command::Toggle { target, .. }
+        | Command::On { target, .. }
+        | Command::Send { target, .. }
+        | Command::Cc { target, .. }
+        | Command::MonoOn { target, .. }
+        | Command::AllSoundOff(target)
+        | Command::ResetControllers(target)
+        | Command::AllNotesOff(target)
+        | Command::OmniOff(target)
+        | Command::OmniOn(target)
+        | Command::PolyOn(target)
+        | Command::Run { target, .. } => fill_target(target),
+        Command::Route {
+            port,
+            channel: target_channel,
+            ..
+        }
+        | Command::Session {
+            port,
+            channel: target_channel,
+            ..
+        }
+        | Command::Play {
+            port,
+            channel: target_channel,
+            ..
+        }
+        | Command::Record {
+            port,
+            channel: target_channel,
+            ..
+        } => {
+            *port = port.take().or_else(|| setup.port.clone());
+            *target_channel = target_channel.or(channel);
+        }
+        Command::Daemon {
+            port,
+            value,
+            channel: target_channel,
+            ..
+        } => {
+            *port = port.take().or_else(|| setup.port.clone());
+            *target_channel = target_channel.or(channel);
+            if value.is_none() {
+                *value = setup.local.map(|local| ValueArg {
+                    value: local,
+                    warning: None,
+                });
+            }
+        }
+        Command::Sysex {
+            port, dump: None, ..
+        } => {
+            *port = port.take().or_else(|| setup.port.clone());
+        }
+        Command::Identify { port, .. } | Command::Monitor { port, .. } => {
+            *port = port.take().or_else(|| setup.port.clone());
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Prints the config file's setups and what the selected one merges to with the flags
+/// A config file that cannot be read or parsed is reported rather than failing
+fn show_config(overrides: Setup, settings: &Settings) -> Result<(), PianoffError> {
+    let (path, config) = load_config(settings.config.as_deref());
+    match &path {
+        Some(path) if path.is_file() => println!("Config file: {}", path.display()),
+        Some(path) => println!("Config file: {} (not found)", path.display()),
+        None => println!("Config file: none (no home directory)"),
+    }
+
+    let config = match config {
+        Ok(config) if config.setups.is_empty() => {
+            println!("Profiles: none");
+            Some(config)
+        }
+        Ok(config) => {
+            println!("Profiles:");
+            for (name, setup) in &config.setups {
+                println!("  {} = {}", name, setup);
+            }
+            Some(config)
+        }
+        Err(e) => {
+            println!("Profiles: unavailable ({})", e);
+            None
+        }
+    };
+
+    let setup = match (&settings.profile, &config) {
+        (Some(name), Some(config)) => {
+            println!("Selected profile: {}", name);
+            config.setup(name)?.clone()
+        }
+        (Some(name), None) => {
+            println!("Selected profile: {} (not applied)", name);
+            Setup::default()
+        }
+        (None, _) => Setup::default(),
+    };
+    println!("Merged settings: {}", overrides.or(&setup));
+    Ok(())
+}
+
+/// Formats a port list entry, with the device profile's name if any
+fn port_entry(index: usize, port_name: &str, profile: Option<&DeviceProfile>) -> String {
+    match profile {
+        Some(profile) => format!("{}: {} [{}]", index, port_name, profile.name),
+        None => format!("{}: {}", index, port_name),
+    }
+}
+
+/// Finds the device profile for a connected port: by port name, or with `--identify`
+/// by asking the device for its identity
+fn detect_profile(
+    connection: &mut MidiOutputConnection,
+    port_name: &str,
+    settings: &Settings,
+) -> Option<DeviceProfile> {
+    if let Some(profile) = settings.profiles.for_port(port_name) {
+        return Some(profile.clone());
+    }
+    if !settings.identify {
+        return None;
+    }
+
+    match identify_device(connection, port_name, IDENTIFY_TIMEOUT) {
+        Ok(identity) => {
+            println!("Device identity: {}", identity);
+            settings.profiles.for_identity(&identity).cloned()
+        }
+        Err(e) => {
+            eprintln!("Note: {}", e);
+            None
+        }
+    }
+}
+
+/// Prints which profile applies to the selected port, with its quirks
+fn announce_profile(profile: &DeviceProfile) {
+    println!(
+        "Device profile: {} (Local Control via {}, channel {})",
+        profile.name,
+        profile.local_control.describe(),
+        display_channel(profile.channel)
+    );
+    for quirk in &profile.quirks {
+        println!("  Note: {}", quirk);
+    }
+}
 
 /// Lists available MIDI output ports and prompts user for selection
-/// Returns an established MIDI connection or error
-fn list_and_select_port() -> Result<MidiOutputConnection, Box<dyn Error>> {
-    let midi_out = MidiOutput::new("MIDI CC Sender")?;
+/// Returns an established MIDI connection and the port's device profile, if any
+fn list_and_select_port(
+    settings: &Settings,
+    last_port: Option<&str>,
+) -> Result<(MidiOutputConnection, String, Option<DeviceProfile>), PianoffError> {
+    let midi_out = open_midi_output()?;
 
     // Get available output ports
     let out_ports = midi_out.ports();
 
     // Handle case when no MIDI ports are available
     if out_ports.is_empty() {
-        return Err("No MIDI output ports available.".into());
+        return Err(PianoffError::NoPorts);
     }
 
     // Display available ports with numbered list
+    let port_names = port_names(&midi_out, &out_ports);
     println!("Available MIDI ports:");
-    for (i, port) in out_ports.iter().enumerate() {
-        let port_name = midi_out
-            .port_name(port)
-            .unwrap_or_else(|_| format!("Unknown Port {}", i));
-        println!("{}: {}", i, port_name);
+    for (i, port_name) in port_names.iter().enumerate() {
+        println!(
+            "{}",
+            port_entry(i, port_name, settings.profiles.for_port(port_name))
+        );
     }
 
-    // Prompt user for port selection
-    print!("Select a port by number: ");
-    io::stdout().flush()?;
-
-    let mut input = String::new();
-    io::stdin().read_line(&mut input)?;
+    // Offer the last used port, or say why it is not offered
+    let last_index = last_port.and_then(|last| port_names.iter().position(|name| name == last));
+    if let (Some(last), None) = (last_port, last_index) {
+        println!("Note: The last used port '{}' is not connected.", last);
+    }
 
-    // Parse and validate port selection
-    let port_index: usize = input
-        .trim()
-        .parse()
-        .map_err(|_| "Invalid input: Please enter a valid number")?;
+    // Prompt user for port selection
+    let input = match last_index {
+        Some(index) => prompt(&format!(
+            "Select a port by number or name (default {}: {}): ",
+            index, port_names[index]
+        ))?,
+        None => prompt("Select a port by number or name: ")?,
+    };
 
-    if port_index >= out_ports.len() {
-        return Err(format!(
-            "Invalid port selection: Port {} does not exist. Available ports: 0-{}",
-            port_index,
-            out_ports.len() - 1
-        )
-        .into());
-    }
+    // Match the selection against the port names
+    let port_index = match last_index {
+        Some(index) if input.trim().is_empty() => index,
+        _ => find_port(&port_names, &input)?,
+    };
 
     // Establish connection to selected port
     let selected_port = &out_ports[port_index];
-    let port_name = midi_out
-        .port_name(selected_port)
-        .unwrap_or_else(|_| format!("Port {}", port_index));
+    let port_name = &port_names[port_index];
 
-    let connection = midi_out
-        .connect(selected_port, &format!("midi-cc-sender-{}", port_index))
-        .map_err(|e| format!("Failed to connect to MIDI port '{}': {}", port_name, e))?;
+    let mut connection = connect_output(midi_out, selected_port, port_name)?;
 
     println!("Connected to MIDI port: {}", port_name);
 
-    Ok(connection)
+    let profile = detect_profile(&mut connection, port_name, settings);
+    if let Some(profile) = &profile {
+        announce_profile(profile);
+    }
+
+    Ok((connection, port_name.clone(), profile))
 }
 
 /// Prompts user for MIDI value and channel with validation and default handling
-/// Returns tuple of (value, channel) or error
-fn get_user_input() -> Result<(u8, u8), Box<dyn Error>> {
-    // Get MIDI value (0-127)
-    print!("Enter MIDI value (0-127, default 0): ");
-    io::stdout().flush()?;
+/// The default value comes from the options, then the last run, then 0; the default
+/// channel from the options, then the last run on the same port, the device profile, 0
+/// Returns tuple of (value, channel) or error; strict mode rejects unusable input
+fn get_user_input(
+    options: &WizardOptions,
+    profile: Option<&DeviceProfile>,
+    last_used: Option<&LastUsed>,
+    port_name: &str,
+) -> Result<(u8, u8), PianoffError> {
+    let mode = options.mode();
+    let default_value = options
+        .default_value
+        .or(last_used.map(|last| last.value))
+        .unwrap_or(0);
+    let default_channel = options
+        .wire_default_channel()?
+        .or(last_used
+            .filter(|last| last.port == port_name)
+            .map(|last| last.channel))
+        .or(profile.map(|profile| profile.channel))
+        .unwrap_or(0);
 
-    let mut input = String::new();
-    io::stdin().read_line(&mut input)?;
+    // Get MIDI value (0-127)
+    let input = prompt(&format!(
+        "Enter MIDI value (0-127, on/off, 0x7F or 50%; default {}): ",
+        default_value
+    ))?;
 
-    let (value, warning) = validate_midi_value(&input);
+    let (value, warning) = validate_midi_value_with(&input, default_value, mode)?;
     if let Some(warning_msg) = warning {
         println!("{}", warning_msg);
     }
 
-    // Get MIDI channel (0-15)
-    print!("Enter MIDI channel (0-15, default 0): ");
-    io::stdout().flush()?;
-
-    let mut input = String::new();
-    io::stdin().read_line(&mut input)?;
+    // Get MIDI channel (0-15, or 1-16 with one-based numbering)
+    let numbering = channel_numbering();
+    let input = prompt(&format!(
+        "Enter MIDI channel ({}-{}, default {}): ",
+        numbering.first(),
+        numbering.last(),
+        numbering.display(default_channel)
+    ))?;
 
-    let (channel, warning) = validate_midi_channel(&input);
+    let (channel, warning) = validate_midi_channel_with(&input, default_channel, mode)?;
     if let Some(warning_msg) = warning {
         println!("{}", warning_msg);
     }
@@ -96,45 +456,964 @@ fn get_user_input() -> Result<(u8, u8), Box<dyn Error>> {
     let control_state = interpret_local_control_value(value);
     println!(
         "Using MIDI value: {} ({}) on channel: {}",
-        value, control_state, channel
+        value,
+        control_state,
+        display_channel(channel)
     );
 
     Ok((value, channel))
 }
 
-/// Creates and sends MIDI Control Change message #122 (Local Control)
-/// Displays confirmation message and handles transmission errors
-fn send_midi_cc_122(
-    connection: &mut MidiOutputConnection,
+/// Prints every available MIDI output port with its index and device profile
+fn list_ports(profiles: &ProfileDb) -> Result<(), PianoffError> {
+    let midi_out = open_midi_output()?;
+    let out_ports = midi_out.ports();
+
+    if out_ports.is_empty() {
+        println!("No MIDI output ports available.");
+        return Ok(());
+    }
+
+    for (i, port_name) in port_names(&midi_out, &out_ports).iter().enumerate() {
+        println!("{}", port_entry(i, port_name, profiles.for_port(port_name)));
+    }
+
+    Ok(())
+}
+
+/// Asks one port, or every port, for its SysEx identity
+/// Prints each port with the identity and the profile it selects
+fn run_identify(
+    selector: Option<&str>,
+    timeout: Duration,
+    profiles: &ProfileDb,
+) -> Result<(), PianoffError> {
+    let midi_out = open_midi_output()?;
+    let out_ports = midi_out.ports();
+    if out_ports.is_empty() {
+        return Err(PianoffError::NoPorts);
+    }
+    let names = port_names(&midi_out, &out_ports);
+
+    let indices = match selector {
+        Some(selector) => vec![find_port(&names, selector)?],
+        None => (0..names.len()).collect(),
+    };
+
+    let mut last_error = None;
+    for index in indices {
+        let result = open_midi_output()
+            .and_then(|midi_out| connect_output(midi_out, &out_ports[index], &names[index]))
+            .and_then(|mut connection| identify_device(&mut connection, &names[index], timeout));
+
+        match result {
+            Ok(identity) => {
+                let profile = identity_profile(profiles, &identity, &names[index]);
+                println!("{}", port_entry(index, &names[index], profile));
+                println!("   {}", identity);
+            }
+            Err(e) => {
+                println!(
+                    "{}",
+                    port_entry(index, &names[index], profiles.for_port(&names[index]))
+                );
+                println!("   {}", e);
+                last_error = Some(e);
+            }
+        }
+    }
+
+    // A single port that does not answer is an error; when scanning, silence is normal
+    match (selector, last_error) {
+        (Some(_), Some(e)) => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// The profile for an identified device: by identity first, then by port name
+fn identity_profile<'a>(
+    profiles: &'a ProfileDb,
+    identity: &DeviceIdentity,
+    port_name: &str,
+) -> Option<&'a DeviceProfile> {
+    profiles
+        .for_identity(identity)
+        .or_else(|| profiles.for_port(port_name))
+}
+
+/// Where a command-line target sends its messages
+struct OpenSink {
+    sink: Box<dyn MidiSink>,
+    /// Port name, or None when dumping hex
+    port_name: Option<String>,
+    profile: Option<DeviceProfile>,
+}
+
+/// Opens the sink described by a command-line target, with the port's device profile
+/// A `--dump` path replaces the MIDI port with a hex-dump file ("-" for stdout)
+fn open_sink(target: &Target, settings: &Settings) -> Result<OpenSink, PianoffError> {
+    let (sink, port_name, profile): (Box<dyn MidiSink>, _, _) = match target.dump.as_deref() {
+        Some(path) if path == Path::new("-") => (Box::new(HexDumpSink::stdout()), None, None),
+        Some(path) => (Box::new(HexDumpSink::create(path)?), None, None),
+        None => {
+            let (mut connection, port_name) = connect_target(target)?;
+            let profile = detect_profile(&mut connection, &port_name, settings);
+            if let Some(profile) = &profile {
+                announce_profile(profile);
+            }
+            (Box::new(connection), Some(port_name), profile)
+        }
+    };
+
+    Ok(OpenSink {
+        sink,
+        port_name,
+        profile,
+    })
+}
+
+/// Sends through a sink that keeps what is sent, then writes it to the `--save-smf`
+/// file, if one was given; the file is written when sending fails part way too, so
+/// it shows what did go out
+fn recording_smf<S: MidiSink, T>(
+    sink: S,
+    settings: &Settings,
+    send: impl FnOnce(&mut SmfRecorder<S>) -> Result<T, PianoffError>,
+) -> Result<T, PianoffError> {
+    let smf = SmfWriter::new(DEFAULT_PPQ, DEFAULT_TEMPO)?.track_name("pianoff");
+    let mut recorder = SmfRecorder::new(sink, smf);
+    let sent = send(&mut recorder);
+
+    let saved = match &settings.save_smf {
+        Some(path) => recorder.smf().save(path).map(|()| {
+            println!(
+                "Saved {} messages to {}",
+                recorder.smf().len(),
+                path.display()
+            );
+        }),
+        None => Ok(()),
+    };
+    // A failed send is the error worth reporting
+    let sent = sent?;
+    saved.map(|()| sent)
+}
+
+/// Connects to the port described by a command-line target
+/// Returns the connection and the port name
+fn connect_target(target: &Target) -> Result<(MidiOutputConnection, String), PianoffError> {
+    let midi_out = open_midi_output()?;
+    let (port, port_name) = resolve_port(&midi_out, target.port.as_deref())?;
+
+    let connection = connect_output(midi_out, &port, &port_name)?;
+
+    println!("Connected to MIDI port: {}", port_name);
+
+    Ok((connection, port_name))
+}
+
+/// Prints the controller names accepted by the `cc` subcommand
+fn list_controllers() {
+    for (name, number) in KNOWN_CONTROLLERS {
+        println!("{:>3}: {}", number, name);
+    }
+}
+
+/// Prints every known device profile, in the order they are matched
+fn list_profiles(profiles: &ProfileDb) {
+    for profile in &profiles.profiles {
+        let key = match (&profile.port, &profile.identity) {
+            (Some(port), _) => format!("port {}", port),
+            (None, Some(identity)) => format!("identity {}", format_identity_key(identity)),
+            (None, None) => String::new(),
+        };
+        println!(
+            "{} ({}): Local Control via {}, channel {}",
+            profile.name,
+            key,
+            profile.local_control.describe(),
+            display_channel(profile.channel)
+        );
+        for quirk in &profile.quirks {
+            println!("  Note: {}", quirk);
+        }
+    }
+}
+
+/// Formats an identity pattern as manufacturer bytes plus optional family and model
+fn format_identity_key(identity: &IdentityPattern) -> String {
+    let mut key = format_hex(&identity.manufacturer);
+    if let Some(family) = identity.family {
+        key.push_str(&format!(" family {:04X}", family));
+    }
+    if let Some(model) = identity.model {
+        key.push_str(&format!(" model {:04X}", model));
+    }
+    key
+}
+
+/// The channel to send on: `--channel`, else the device profile's, else 0
+fn target_channel(explicit: Option<u8>, profile: Option<&DeviceProfile>) -> u8 {
+    explicit
+        .or(profile.map(|profile| profile.channel))
+        .unwrap_or(0)
+}
+
+/// Switches Local Control on the sink described by a command-line target
+/// Devices whose profile asks for SysEx get that instead of CC #122, and refuse
+/// `--all-channels`; otherwise that sends CC #122 on every channel, paced by `--delay`
+fn run_local_control(
+    value: u8,
+    target: &Target,
+    broadcast: &Broadcast,
+    settings: &Settings,
+) -> Result<(), PianoffError> {
+    let channel = target.wire_channel()?;
+    let OpenSink {
+        sink,
+        port_name,
+        profile,
+    } = open_sink(target, settings)?;
+    let channel = target_channel(channel, profile.as_ref());
+    if let Some(profile) = &profile
+        && broadcast.all_channels
+        && matches!(profile.local_control, LocalControlMethod::Sysex { .. })
+    {
+        return Err(PianoffError::LocalControlUnsupported {
+            device: profile.name.clone(),
+            reason: "--all-channels needs CC #122, but it is switched by SysEx; leave \
+                     --all-channels out"
+                .to_string(),
+        });
+    }
+
+    recording_smf(sink, settings, |sink| {
+        let (confirmation, channels) = match &profile {
+            Some(profile) if profile.local_control != LocalControlMethod::Cc => (
+                send_local_control(sink, profile, value, channel)?,
+                vec![channel],
+            ),
+            _ if broadcast.all_channels => {
+                let pacing = Duration::from_millis(broadcast.delay);
+                (
+                    send_midi_cc_122_batch(sink, value, ALL_CHANNELS, pacing)?,
+                    ALL_CHANNELS.collect(),
+                )
+            }
+            _ => (send_midi_cc_122(sink, value, channel)?, vec![channel]),
+        };
+        println!("{}", confirmation);
+        if let Some(port_name) = &port_name {
+            record_local_control(settings, port_name, &channels, value);
+        }
+        Ok(())
+    })
+}
+
+/// Sends the opposite of the Local Control value last sent to the target's port and
+/// channel, or of `assume`; an unknown state counts as On, the power-on default
+fn run_toggle(
+    target: &Target,
+    assume: Option<LocalState>,
+    settings: &Settings,
+) -> Result<(), PianoffError> {
+    let channel = target.wire_channel()?;
+    let OpenSink {
+        sink,
+        port_name,
+        profile,
+    } = open_sink(target, settings)?;
+    let channel = target_channel(channel, profile.as_ref());
+
+    let tracked = port_name
+        .as_deref()
+        .and_then(|port_name| load_state(settings).local_control(port_name, channel));
+    let current = match (assume, tracked) {
+        (Some(state), _) => state.value(),
+        (None, Some(value)) => value,
+        (None, None) => {
+            println!(
+                "Note: No Local Control state is known for this port and channel; \
+                 assuming On. Use --assume to override."
+            );
+            127
+        }
+    };
+    let value = toggled_local_control_value(current);
+
+    recording_smf(sink, settings, |sink| {
+        let confirmation = switch_local_control(sink, profile.as_ref(), value, channel)?;
+        println!(
+            "{} -> {}",
+            interpret_local_control_value(current),
+            interpret_local_control_value(value)
+        );
+        println!("{}", confirmation);
+        if let Some(port_name) = &port_name {
+            record_local_control(settings, port_name, &[channel], value);
+        }
+        Ok(())
+    })
+}
+
+/// Switches Local Control the way the device profile asks, or with CC #122
+fn switch_local_control(
+    sink: &mut dyn MidiSink,
+    profile: Option<&DeviceProfile>,
     value: u8,
     channel: u8,
-) -> Result<(), Box<dyn Error>> {
-    // Create MIDI Control Change message using helper function
-    let midi_message = create_midi_cc_122_message(value, channel)?;
+) -> Result<String, PianoffError> {
+    match profile {
+        Some(profile) => send_local_control(sink, profile, value, channel),
+        None => send_midi_cc_122(sink, value, channel),
+    }
+}
 
-    // Send the message through the MIDI connection
-    connection
-        .send(&midi_message)
-        .map_err(|e| format!("Failed to send MIDI message: {}", e))?;
+/// Connects to the piano's output port and looks up its device profile
+fn connect_piano(
+    selector: Option<&str>,
+    settings: &Settings,
+) -> Result<(MidiOutputConnection, String, Option<DeviceProfile>), PianoffError> {
+    let midi_out = open_midi_output()?;
+    let (port, port_name) = resolve_port(&midi_out, selector)?;
+    let mut piano = connect_output(midi_out, &port, &port_name)?;
+    println!("Connected to MIDI port: {}", port_name);
 
-    // Display confirmation message
-    let control_state = interpret_local_control_value(value);
-    let control_display = if value == 0 || value == 127 {
-        control_state.to_string()
+    let profile = detect_profile(&mut piano, &port_name, settings);
+    if let Some(profile) = &profile {
+        announce_profile(profile);
+    }
+    Ok((piano, port_name, profile))
+}
+
+/// Turns Local Control off and returns a guard that turns it back on when dropped
+fn local_control_off_guarded<S: MidiSink>(
+    piano: S,
+    profile: Option<&DeviceProfile>,
+    channel: u8,
+    all_notes_off: bool,
+) -> Result<LocalControlGuard<S>, PianoffError> {
+    let guard = LocalControlGuard::new(piano, channel)?.all_notes_off(all_notes_off);
+    let mut guard = match profile.map(|profile| &profile.local_control) {
+        Some(LocalControlMethod::Sysex { on, .. }) => guard.restore_with(on.clone()),
+        _ => guard,
+    };
+    let confirmation =
+        guard.switch_off(|piano| switch_local_control(piano, profile, 0, channel))?;
+    println!("{}", confirmation);
+    Ok(guard)
+}
+
+/// Timestamped record of what the daemon does, on stdout or appended to a file
+struct ActionLog(Box<dyn Write>);
+
+impl ActionLog {
+    fn open(path: Option<&Path>) -> Result<Self, PianoffError> {
+        match path {
+            Some(path) => fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .map(|file| ActionLog(Box::new(file)))
+                .map_err(|e| {
+                    PianoffError::io(format!("Failed to open log file '{}'", path.display()), e)
+                }),
+            None => Ok(ActionLog(Box::new(io::stdout()))),
+        }
+    }
+
+    /// Writes one entry; a log that cannot be written must not stop the daemon
+    fn write(&mut self, message: &str) {
+        let _ = writeln!(self.0, "{}", format_log_line(SystemTime::now(), message));
+        let _ = self.0.flush();
+    }
+}
+
+/// Connects to a watched port, by name and position among ports of that name, and
+/// switches Local Control on it
+fn apply_local_control(
+    port: &WatchedPort,
+    value: u8,
+    channel: Option<u8>,
+    settings: &Settings,
+) -> Result<String, PianoffError> {
+    let midi_out = open_midi_output()?;
+    let ports = midi_out.ports();
+    let names = port_names(&midi_out, &ports);
+    let index = port
+        .find(&names)
+        .ok_or_else(|| PianoffError::PortNotFound {
+            selector: port.to_string(),
+            available: names.clone(),
+        })?;
+    let mut connection = connect_output(midi_out, &ports[index], &port.name)?;
+
+    let profile = settings.profiles.for_port(&port.name);
+    let channel = target_channel(channel, profile);
+    let confirmation = switch_local_control(&mut connection, profile, value, channel)?;
+    record_local_control(settings, &port.name, &[channel], value);
+    Ok(confirmation)
+}
+
+/// Watches the port list and sends Local Control to matching ports whenever they
+/// appear, until SIGINT or SIGTERM; never reads stdin
+fn run_daemon(
+    selector: &str,
+    value: u8,
+    channel: Option<u8>,
+    debounce: Duration,
+    interval: Duration,
+    log_path: Option<&Path>,
+    settings: &Settings,
+) -> Result<(), PianoffError> {
+    let channel = cli::wire_channel(channel)?;
+    let mut watcher = PortWatcher::new(selector, debounce)?;
+    let mut log = ActionLog::open(log_path)?;
+    let stop = interrupt_flag()?;
+    let midi_out = open_midi_output()?;
+
+    log.write(&format!(
+        "Watching for ports matching '{}' to send {} (value {})",
+        selector,
+        interpret_local_control_value(value),
+        value
+    ));
+    while !stop.load(Ordering::SeqCst) {
+        let names = port_names(&midi_out, &midi_out.ports());
+        for event in watcher.update(&names, Instant::now()) {
+            log.write(&event.to_string());
+            if let PortEvent::Ready(port) = event {
+                match apply_local_control(&port, value, channel, settings) {
+                    Ok(confirmation) => log.write(&format!("{}: {}", port, confirmation)),
+                    Err(e) => {
+                        log.write(&format!("{}: {}; retrying", port, e));
+                        watcher.retry(&port, Instant::now());
+                    }
+                }
+            }
+        }
+        thread::sleep(interval);
+    }
+    log.write("Stopping");
+    Ok(())
+}
+
+/// Turns Local Control off and keeps it off until SIGINT or SIGTERM, then turns it
+/// back on; a crash or any other exit restores it too
+fn run_session(
+    selector: Option<&str>,
+    channel: Option<u8>,
+    all_notes_off: bool,
+    settings: &Settings,
+) -> Result<(), PianoffError> {
+    let channel = cli::wire_channel(channel)?;
+    let (piano, port_name, profile) = connect_piano(selector, settings)?;
+    let channel = target_channel(channel, profile.as_ref());
+
+    let stop = interrupt_flag()?;
+    recording_smf(piano, settings, |piano| {
+        let guard = local_control_off_guarded(piano, profile.as_ref(), channel, all_notes_off)?;
+        println!("Local Control stays off until Ctrl-C");
+        while !stop.load(Ordering::SeqCst) {
+            thread::sleep(ROUTE_POLL_INTERVAL);
+        }
+
+        println!("{}", guard.restore()?);
+        record_local_control(settings, &port_name, &[channel], 127);
+        Ok(())
+    })
+}
+
+/// Turns Local Control off, forwards the piano's input to the outputs until Ctrl-C,
+/// then turns Local Control back on
+fn run_route(
+    selector: Option<&str>,
+    channel: Option<u8>,
+    output_selectors: &[String],
+    thru: bool,
+    all_notes_off: bool,
+    settings: &Settings,
+) -> Result<(), PianoffError> {
+    let channel = cli::wire_channel(channel)?;
+    let (piano, port_name, profile) = connect_piano(selector, settings)?;
+    let channel = target_channel(channel, profile.as_ref());
+
+    let (_input, messages) = connect_input(&port_name)?;
+    let mut outputs = Vec::new();
+    let mut output_names = Vec::new();
+    for selector in output_selectors {
+        let midi_out = open_midi_output()?;
+        let (port, name) = resolve_port(&midi_out, Some(selector))?;
+        outputs.push(connect_output(midi_out, &port, &name)?);
+        output_names.push(name);
+    }
+    if thru {
+        output_names.push(port_name.clone());
+    }
+
+    let stop = interrupt_flag()?;
+    // `--save-smf` keeps what goes to the piano: Local Control and the `--thru` copy
+    recording_smf(piano, settings, |piano| {
+        let mut guard = local_control_off_guarded(piano, profile.as_ref(), channel, all_notes_off)?;
+        println!(
+            "Routing {} to {} (Ctrl-C to stop)",
+            port_name,
+            output_names.join(", ")
+        );
+
+        let routed = {
+            let mut sinks: Vec<&mut dyn MidiSink> = outputs
+                .iter_mut()
+                .map(|output| output as &mut dyn MidiSink)
+                .collect();
+            if thru {
+                sinks.push(guard.sink());
+            }
+            route_messages(&messages, &mut sinks, &stop)
+        };
+
+        // Local Control goes back on even when forwarding failed
+        println!("{}", guard.restore()?);
+        record_local_control(settings, &port_name, &[channel], 127);
+        println!("Forwarded {} messages", routed?);
+        Ok(())
+    })
+}
+
+/// Plays a Standard MIDI File to a port given by selector or chosen from the port
+/// list, with Local Control off while playing if asked to
+fn run_play(
+    path: &Path,
+    selector: Option<&str>,
+    channel: Option<u8>,
+    local_off: bool,
+    settings: &Settings,
+) -> Result<(), PianoffError> {
+    let smf = Smf::load(path)?;
+    let messages = smf.timed_messages();
+    let channel = cli::wire_channel(channel)?;
+
+    let (piano, port_name, profile) = match selector {
+        Some(selector) => connect_piano(Some(selector), settings)?,
+        None => {
+            let last_used = load_state(settings).last_used;
+            list_and_select_port(settings, last_used.as_ref().map(|last| last.port.as_str()))?
+        }
+    };
+    let channel = target_channel(channel, profile.as_ref());
+
+    let stop = interrupt_flag()?;
+    let duration = Duration::from_micros(messages.last().map_or(0, |(micros, _)| *micros));
+    let play = |sink: &mut dyn MidiSink| {
+        println!(
+            "Playing {} ({} messages, {}:{:02}; Ctrl-C to stop)",
+            path.display(),
+            messages.len(),
+            duration.as_secs() / 60,
+            duration.as_secs() % 60
+        );
+        let played = play_messages(sink, &messages, &stop);
+        // Notes cut off by Ctrl-C would otherwise keep sounding
+        for channel in smf.channels() {
+            sink.send(&create_channel_mode_message(
+                ChannelMode::AllNotesOff,
+                channel,
+            )?)?;
+        }
+        played
+    };
+
+    let played = recording_smf(piano, settings, |piano| {
+        if local_off {
+            let mut guard = local_control_off_guarded(piano, profile.as_ref(), channel, false)?;
+            let played = play(guard.sink());
+            // Local Control goes back on even when playing failed
+            println!("{}", guard.restore()?);
+            record_local_control(settings, &port_name, &[channel], 127);
+            played
+        } else {
+            play(piano)
+        }
+    })?;
+
+    if played < messages.len() {
+        println!("Stopped after {} of {} messages", played, messages.len());
+    } else {
+        println!("Played {} messages", played);
+    }
+    Ok(())
+}
+
+/// Selects the output port named exactly like an input port, for devices that show
+/// up as both; a looser match could pick another device
+fn output_named_like(input_name: &str) -> Result<String, PianoffError> {
+    let midi_out = open_midi_output()?;
+    let names = port_names(&midi_out, &midi_out.ports());
+    match names.iter().position(|name| name == input_name) {
+        Some(index) => Ok(index.to_string()),
+        None => Err(PianoffError::NoOutputForInput {
+            input: input_name.to_string(),
+            available: names,
+        }),
+    }
+}
+
+/// Records an input port to a Standard MIDI File until Ctrl-C
+/// With `local_off`, Local Control is off meanwhile on the output port it selects, or
+/// else on the output port of the same name
+fn run_record(
+    path: &Path,
+    selector: Option<&str>,
+    channel: Option<u8>,
+    local_off: Option<Option<&str>>,
+    mut smf: SmfWriter,
+    trim: bool,
+    settings: &Settings,
+) -> Result<(), PianoffError> {
+    let channel = cli::wire_channel(channel)?;
+    let (_input, messages, port_name) = select_input_port(selector)?;
+
+    let stop = interrupt_flag()?;
+    let guard = if let Some(output_selector) = local_off {
+        let output_selector = match output_selector {
+            Some(selector) => selector.to_string(),
+            None => output_named_like(&port_name)?,
+        };
+        let (piano, piano_name, profile) = connect_piano(Some(&output_selector), settings)?;
+        let channel = target_channel(channel, profile.as_ref());
+        let guard = local_control_off_guarded(piano, profile.as_ref(), channel, false)?;
+        Some((guard, piano_name, channel))
     } else {
-        format!("Local Control Value {}", value)
+        None
     };
 
-    println!(
-        "✓ Successfully sent MIDI CC #122: {} (value: {}) on channel {}",
-        control_display, value, channel
-    );
+    println!("Recording {} (Ctrl-C to stop)", port_name);
+    let start = (!trim).then(Instant::now);
+    let recorded = record_messages(&messages, &mut smf, start, &stop);
+
+    // The take is saved even when Local Control cannot be turned back on
+    let restored = guard.map(|(guard, piano_name, channel)| {
+        guard.restore().map(|confirmation| {
+            println!("{}", confirmation);
+            record_local_control(settings, &piano_name, &[channel], 127);
+        })
+    });
+    smf.save(path)?;
+    println!("Saved {} messages to {}", recorded, path.display());
+    restored.unwrap_or(Ok(()))
+}
+
+/// Runs a script against the sink described by a command-line target, printing each
+/// message as it is sent; lines without `ch=` use the target's channel
+fn run_script(path: &Path, target: &Target, settings: &Settings) -> Result<(), PianoffError> {
+    let script = Script::load(path)?;
+    for warning in &script.warnings {
+        eprintln!("{}", warning);
+    }
+
+    let channel = target.wire_channel()?;
+    let OpenSink {
+        sink,
+        port_name,
+        profile,
+    } = open_sink(target, settings)?;
+    let channel = target_channel(channel, profile.as_ref());
+
+    recording_smf(sink, settings, |sink| {
+        let mut sent = 0;
+        for ScriptLine { line, step } in &script.steps {
+            let Some(message) = step.message(channel) else {
+                if let ScriptStep::Wait(duration) = step {
+                    thread::sleep(*duration);
+                }
+                continue;
+            };
+
+            sink.send(&message.to_bytes()?)?;
+            sent += 1;
+            let (kind, values) = describe_message(&message);
+            match message.channel() {
+                Some(channel) => println!(
+                    "Line {}: {} {} on channel {}",
+                    line,
+                    kind,
+                    values,
+                    display_channel(channel)
+                ),
+                None => println!("Line {}: {} {}", line, kind, values),
+            }
 
+            if let (
+                Some(port_name),
+                MidiMessage::ControlChange {
+                    channel,
+                    controller: 122,
+                    value,
+                },
+            ) = (&port_name, &message)
+            {
+                record_local_control(settings, port_name, &[*channel], *value);
+            }
+        }
+
+        println!(
+            "✓ Successfully ran {}: {} messages sent",
+            path.display(),
+            sent
+        );
+        Ok(())
+    })
+}
+
+/// Sends a Channel Mode message to the sink described by a command-line target
+fn run_channel_mode(
+    mode: ChannelMode,
+    target: &Target,
+    settings: &Settings,
+) -> Result<(), PianoffError> {
+    let channel = target.wire_channel()?;
+    let OpenSink { sink, profile, .. } = open_sink(target, settings)?;
+    let channel = target_channel(channel, profile.as_ref());
+    recording_smf(sink, settings, |sink| {
+        println!("{}", send_channel_mode(sink, mode, channel)?);
+        Ok(())
+    })
+}
+
+/// Connects to an input port chosen by selector, the only port, or a numbered prompt
+/// Each message arrives with its backend timestamp in microseconds
+fn select_input_port(
+    selector: Option<&str>,
+) -> Result<(MidiInputConnection<()>, Receiver<TimedMessage>, String), PianoffError> {
+    let midi_in = open_midi_input()?;
+    let in_ports = midi_in.ports();
+    if in_ports.is_empty() {
+        return Err(PianoffError::NoPorts);
+    }
+
+    let names = port_names(&midi_in, &in_ports);
+    let index = match selector {
+        Some(selector) => find_port(&names, selector)?,
+        None if in_ports.len() == 1 => 0,
+        None => {
+            println!("Available MIDI input ports:");
+            for (i, name) in names.iter().enumerate() {
+                println!("{}: {}", i, name);
+            }
+            find_port(&names, &prompt("Select a port by number or name: ")?)?
+        }
+    };
+
+    let (connection, messages) =
+        listen(midi_in, &in_ports[index], &names[index], |time, bytes| {
+            (time, bytes.to_vec())
+        })?;
+    Ok((connection, messages, names[index].clone()))
+}
+
+/// Prints incoming messages until the process is interrupted
+fn run_monitor(
+    selector: Option<&str>,
+    types: Vec<MessageType>,
+    channels: Vec<u8>,
+    raw: bool,
+) -> Result<(), PianoffError> {
+    let channels = channels
+        .into_iter()
+        .map(|channel| channel_numbering().from_display(channel))
+        .collect::<Result<Vec<_>, _>>()?;
+    let filter = MonitorFilter { types, channels };
+
+    let (_connection, messages, port_name) = select_input_port(selector)?;
+    println!("Monitoring {} (Ctrl-C to stop)", port_name);
+
+    let mut start = None;
+    for (time, bytes) in messages {
+        let start = *start.get_or_insert(time);
+        if let Some(line) = format_monitor_line(time.saturating_sub(start), &bytes, &filter, raw) {
+            println!("{}", line);
+        }
+    }
     Ok(())
 }
 
-/// Main application function that orchestrates the complete workflow
-fn main() -> Result<(), Box<dyn Error>> {
+/// Prints the ambiguity warning of a command-line value, if any, and returns the value
+fn value_or_warn(arg: ValueArg) -> u8 {
+    if let Some(warning) = arg.warning {
+        eprintln!("{}", warning);
+    }
+    arg.value
+}
+
+/// Runs a single non-interactive subcommand
+fn run_command(command: Command, settings: &Settings) -> Result<(), PianoffError> {
+    match command {
+        Command::Off { target, broadcast } => run_local_control(0, &target, &broadcast, settings),
+        Command::Toggle { target, assume } => run_toggle(&target, assume, settings),
+        Command::On { target, broadcast } => run_local_control(127, &target, &broadcast, settings),
+        Command::Send {
+            value,
+            target,
+            broadcast,
+        } => run_local_control(value_or_warn(value), &target, &broadcast, settings),
+        Command::Cc {
+            controller,
+            value,
+            target,
+        } => {
+            let channel = target.wire_channel()?;
+            let OpenSink { sink, profile, .. } = open_sink(&target, settings)?;
+            let channel = target_channel(channel, profile.as_ref());
+            let value = value_or_warn(value.for_controller(controller));
+            recording_smf(sink, settings, |sink| {
+                println!("{}", send_cc(sink, controller, value, channel)?);
+                Ok(())
+            })
+        }
+        Command::AllSoundOff(target) => {
+            run_channel_mode(ChannelMode::AllSoundOff, &target, settings)
+        }
+        Command::ResetControllers(target) => {
+            run_channel_mode(ChannelMode::ResetAllControllers, &target, settings)
+        }
+        Command::AllNotesOff(target) => {
+            run_channel_mode(ChannelMode::AllNotesOff, &target, settings)
+        }
+        Command::OmniOff(target) => run_channel_mode(ChannelMode::OmniOff, &target, settings),
+        Command::OmniOn(target) => run_channel_mode(ChannelMode::OmniOn, &target, settings),
+        Command::MonoOn { channels, target } => {
+            run_channel_mode(ChannelMode::MonoOn(channels), &target, settings)
+        }
+        Command::PolyOn(target) => run_channel_mode(ChannelMode::PolyOn, &target, settings),
+        Command::ListPorts => list_ports(&settings.profiles),
+        Command::ListControllers => {
+            list_controllers();
+            Ok(())
+        }
+        Command::ListProfiles => {
+            list_profiles(&settings.profiles);
+            Ok(())
+        }
+        Command::Identify { port, timeout } => run_identify(
+            port.as_deref(),
+            Duration::from_millis(timeout),
+            &settings.profiles,
+        ),
+        Command::Monitor {
+            port,
+            types,
+            channels,
+            raw,
+        } => run_monitor(port.as_deref(), types, channels, raw),
+        Command::Route {
+            port,
+            channel,
+            outputs,
+            thru,
+            all_notes_off,
+        } => run_route(
+            port.as_deref(),
+            channel,
+            &outputs,
+            thru,
+            all_notes_off,
+            settings,
+        ),
+        Command::Daemon {
+            port,
+            value,
+            channel,
+            debounce,
+            interval,
+            log,
+        } => run_daemon(
+            port.as_deref()
+                .ok_or(PianoffError::MissingPort { command: "daemon" })?,
+            value.map(value_or_warn).unwrap_or(0),
+            channel,
+            Duration::from_millis(debounce),
+            Duration::from_millis(interval),
+            log.as_deref(),
+            settings,
+        ),
+        Command::Session {
+            port,
+            channel,
+            all_notes_off,
+        } => run_session(port.as_deref(), channel, all_notes_off, settings),
+        Command::Play {
+            file,
+            port,
+            channel,
+            local_off,
+        } => run_play(&file, port.as_deref(), channel, local_off, settings),
+        Command::Record {
+            file,
+            port,
+            channel,
+            local_off,
+            output_port,
+            bpm,
+            ppq,
+            trim,
+        } => {
+            let smf = SmfWriter::new(ppq, 60_000_000 / bpm)?.track_name("pianoff");
+            run_record(
+                &file,
+                port.as_deref(),
+                channel,
+                local_off.then_some(output_port.as_deref()),
+                smf,
+                trim,
+                settings,
+            )
+        }
+        Command::Run { file, target } => run_script(&file, &target, settings),
+        Command::Sysex {
+            hex,
+            file,
+            delay,
+            port,
+            dump,
+        } => {
+            let messages = match file {
+                Some(path) => SysEx::load(path)?,
+                None => SysEx::split(&parse_hex(&hex.join(" "))?, "the arguments")?,
+            };
+            let target = Target {
+                port,
+                channel: None,
+                dump,
+            };
+            let OpenSink { sink, .. } = open_sink(&target, settings)?;
+            recording_smf(sink, settings, |sink| {
+                println!(
+                    "{}",
+                    send_sysex(sink, &messages, Duration::from_millis(delay))?
+                );
+                Ok(())
+            })
+        }
+        Command::Config {
+            action:
+                ConfigAction::Show {
+                    port,
+                    channel,
+                    value,
+                },
+        } => {
+            let overrides = Setup {
+                port,
+                channel: cli::wire_channel(channel)?,
+                local: value.map(value_or_warn),
+            };
+            show_config(overrides, settings)
+        }
+    }
+}
+
+/// Interactive wizard used when no subcommand is given
+fn run_interactive(options: &WizardOptions, settings: &Settings) -> Result<(), PianoffError> {
     // Display welcome message and instructions
     println!("MIDI Control Change #122 (Local Control) Sender");
     println!("===============================================");
@@ -150,30 +1429,43 @@ fn main() -> Result<(), Box<dyn Error>> {
     // Step 1: Discover and select MIDI port
     println!("Step 1: Select MIDI Output Port");
     println!("-------------------------------");
-    let mut connection = list_and_select_port().map_err(|e| {
-        eprintln!("Failed to establish MIDI connection: {}", e);
-        e
-    })?;
+    // Remembered settings are offered only when no --profile was chosen
+    let last_used = match settings.profile {
+        Some(_) => None,
+        None => load_state(settings).last_used,
+    };
+    let (connection, port_name, profile) =
+        list_and_select_port(settings, last_used.as_ref().map(|last| last.port.as_str()))?;
 
     println!();
 
     // Step 2: Get user input for value and channel
     println!("Step 2: Configure MIDI Parameters");
     println!("---------------------------------");
-    let (value, channel) = get_user_input().map_err(|e| {
-        eprintln!("Failed to get user input: {}", e);
-        e
-    })?;
+    let (value, channel) =
+        get_user_input(options, profile.as_ref(), last_used.as_ref(), &port_name)?;
 
     println!();
 
     // Step 3: Send MIDI message
     println!("Step 3: Send MIDI Message");
     println!("-------------------------");
-    send_midi_cc_122(&mut connection, value, channel).map_err(|e| {
-        eprintln!("Failed to send MIDI message: {}", e);
-        e
+    recording_smf(connection, settings, |connection| {
+        let confirmation = match &profile {
+            Some(profile) => send_local_control(connection, profile, value, channel)?,
+            None => send_midi_cc_122(connection, value, channel)?,
+        };
+        println!("{}", confirmation);
+        Ok(())
     })?;
+    update_state(settings, |state| {
+        state.record_local_control(&port_name, channel, value);
+        state.last_used = Some(LastUsed {
+            port: port_name,
+            value,
+            channel,
+        });
+    });
 
     println!();
     println!("Operation completed successfully!");
@@ -182,3 +1474,73 @@ fn main() -> Result<(), Box<dyn Error>> {
     // Connection is automatically closed when it goes out of scope
     Ok(())
 }
+
+/// Entry point: dispatches to a subcommand or the interactive wizard
+/// Exits with a non-zero status when anything fails
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    if let Err(e) = cli.check() {
+        e.exit();
+    }
+    set_channel_numbering(cli.channel_numbering());
+    let Cli {
+        command,
+        mut wizard,
+        profiles,
+        identify,
+        profile,
+        config,
+        save_smf,
+        ..
+    } = cli;
+
+    let result = load_profiles(profiles.as_deref()).and_then(|profiles| {
+        let settings = Settings {
+            profiles,
+            identify,
+            config,
+            profile,
+            state_path: state_path(),
+            save_smf,
+        };
+
+        match command {
+            Some(mut command) => {
+                apply_setup(&mut command, &settings)?;
+                run_command(command, &settings)
+            }
+            // A profile with a Local Control value replaces the wizard
+            None => {
+                let setup = selected_setup(&settings)?;
+                match setup.local {
+                    Some(value) => {
+                        let target = Target {
+                            port: setup.port,
+                            channel: setup.channel.map(display_channel),
+                            dump: None,
+                        };
+                        let broadcast = Broadcast {
+                            all_channels: false,
+                            delay: 0,
+                        };
+                        run_local_control(value, &target, &broadcast, &settings)
+                    }
+                    None => {
+                        if wizard.default_channel.is_none() {
+                            wizard.default_channel = setup.channel.map(display_channel);
+                        }
+                        run_interactive(&wizard, &settings)
+                    }
+                }
+            }
+        }
+    });
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,416 @@
+use std::error::Error;
+use std::fmt;
+
+/// A single MIDI 1.0 message
+/// Channels are 0-15 and data values are 7-bit unless noted otherwise
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MidiMessage {
+    NoteOff {
+        channel: u8,
+        note: u8,
+        velocity: u8,
+    },
+    NoteOn {
+        channel: u8,
+        note: u8,
+        velocity: u8,
+    },
+    PolyAftertouch {
+        channel: u8,
+        note: u8,
+        pressure: u8,
+    },
+    ControlChange {
+        channel: u8,
+        controller: u8,
+        value: u8,
+    },
+    ProgramChange {
+        channel: u8,
+        program: u8,
+    },
+    ChannelPressure {
+        channel: u8,
+        pressure: u8,
+    },
+    /// 14-bit bend amount, 8192 is the centre position
+    PitchBend {
+        channel: u8,
+        value: u16,
+    },
+    /// System Exclusive payload, without the F0/F7 framing bytes
+    SysEx(Vec<u8>),
+    MtcQuarterFrame(u8),
+    /// 14-bit song position in MIDI beats (sixteenth notes)
+    SongPosition(u16),
+    SongSelect(u8),
+    TuneRequest,
+    TimingClock,
+    Start,
+    Continue,
+    Stop,
+    ActiveSensing,
+    SystemReset,
+}
+
+/// Reasons why bytes or fields do not form a valid MIDI message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MidiMessageError {
+    /// No bytes at all
+    Empty,
+    /// The first byte is a data byte instead of a status byte
+    MissingStatus(u8),
+    /// Status byte reserved by the MIDI specification (F4, F5, F9, FD)
+    UndefinedStatus(u8),
+    /// Fewer bytes than the status byte requires
+    Truncated {
+        status: u8,
+        expected: usize,
+        actual: usize,
+    },
+    /// More bytes than the status byte allows
+    TrailingBytes {
+        status: u8,
+        expected: usize,
+        actual: usize,
+    },
+    /// A byte with the high bit set where a 7-bit data byte was expected
+    InvalidDataByte { position: usize, byte: u8 },
+    /// SysEx that does not end with F7
+    UnterminatedSysEx,
+    /// Bytes expected to be SysEx that start with another status byte
+    NotSysEx(u8),
+    /// Wire channel above 15; the message shows it in the current numbering
+    ChannelOutOfRange(u8),
+    /// Field value larger than its bit width allows
+    ValueOutOfRange {
+        field: &'static str,
+        value: u16,
+        max: u16,
+    },
+}
+
+impl fmt::Display for MidiMessageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MidiMessageError::Empty => write!(f, "Empty MIDI message"),
+            MidiMessageError::MissingStatus(byte) => {
+                write!(f, "Expected a status byte, found data byte 0x{:02X}", byte)
+            }
+            MidiMessageError::UndefinedStatus(byte) => {
+                write!(f, "Undefined MIDI status byte 0x{:02X}", byte)
+            }
+            MidiMessageError::Truncated {
+                status,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "Truncated MIDI message: status 0x{:02X} needs {} bytes, got {}",
+                status, expected, actual
+            ),
+            MidiMessageError::TrailingBytes {
+                status,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "Trailing bytes after MIDI message: status 0x{:02X} needs {} bytes, got {}",
+                status, expected, actual
+            ),
+            MidiMessageError::InvalidDataByte { position, byte } => write!(
+                f,
+                "Invalid data byte 0x{:02X} at position {}: must be 0x00-0x7F",
+                byte, position
+            ),
+            MidiMessageError::UnterminatedSysEx => {
+                write!(f, "System Exclusive message is not terminated by F7")
+            }
+            MidiMessageError::NotSysEx(byte) => {
+                write!(
+                    f,
+                    "Expected a SysEx message starting with F0, found 0x{:02X}",
+                    byte
+                )
+            }
+            MidiMessageError::ChannelOutOfRange(channel) => {
+                let numbering = crate::channel_numbering();
+                write!(
+                    f,
+                    "Invalid MIDI channel: {}. Must be {}-{}.",
+                    u32::from(*channel) + u32::from(numbering.first()),
+                    numbering.first(),
+                    numbering.last()
+                )
+            }
+            MidiMessageError::ValueOutOfRange { field, value, max } => {
+                write!(f, "Invalid {}: {}. Must be 0-{}.", field, value, max)
+            }
+        }
+    }
+}
+
+impl Error for MidiMessageError {}
+
+/// Number of bytes (status included) of a message with the given status
+/// Returns None for SysEx, whose length is variable, and for data bytes
+pub fn message_length(status: u8) -> Option<usize> {
+    match status {
+        0x80..=0xBF | 0xE0..=0xEF => Some(3),
+        0xC0..=0xDF => Some(2),
+        0xF1 | 0xF3 => Some(2),
+        0xF2 => Some(3),
+        0xF6 | 0xF8..=0xFF => Some(1),
+        _ => None,
+    }
+}
+
+impl MidiMessage {
+    /// Returns the channel of channel voice messages
+    pub fn channel(&self) -> Option<u8> {
+        match self {
+            MidiMessage::NoteOff { channel, .. }
+            | MidiMessage::NoteOn { channel, .. }
+            | MidiMessage::PolyAftertouch { channel, .. }
+            | MidiMessage::ControlChange { channel, .. }
+            | MidiMessage::ProgramChange { channel, .. }
+            | MidiMessage::ChannelPressure { channel, .. }
+            | MidiMessage::PitchBend { channel, .. } => Some(*channel),
+            _ => None,
+        }
+    }
+
+    /// Returns the status byte of this message
+    pub fn status(&self) -> u8 {
+        let channel = self.channel().unwrap_or(0) & 0x0F;
+        match self {
+            MidiMessage::NoteOff { .. } => 0x80 | channel,
+            MidiMessage::NoteOn { .. } => 0x90 | channel,
+            MidiMessage::PolyAftertouch { .. } => 0xA0 | channel,
+            MidiMessage::ControlChange { .. } => 0xB0 | channel,
+            MidiMessage::ProgramChange { .. } => 0xC0 | channel,
+            MidiMessage::ChannelPressure { .. } => 0xD0 | channel,
+            MidiMessage::PitchBend { .. } => 0xE0 | channel,
+            MidiMessage::SysEx(_) => 0xF0,
+            MidiMessage::MtcQuarterFrame(_) => 0xF1,
+            MidiMessage::SongPosition(_) => 0xF2,
+            MidiMessage::SongSelect(_) => 0xF3,
+            MidiMessage::TuneRequest => 0xF6,
+            MidiMessage::TimingClock => 0xF8,
+            MidiMessage::Start => 0xFA,
+            MidiMessage::Continue => 0xFB,
+            MidiMessage::Stop => 0xFC,
+            MidiMessage::ActiveSensing => 0xFE,
+            MidiMessage::SystemReset => 0xFF,
+        }
+    }
+
+    /// Checks that every field fits its MIDI bit width
+    pub fn validate(&self) -> Result<(), MidiMessageError> {
+        if let Some(channel) = self.channel()
+            && channel > 15
+        {
+            return Err(MidiMessageError::ChannelOutOfRange(channel));
+        }
+
+        match self {
+            MidiMessage::NoteOff { note, velocity, .. }
+            | MidiMessage::NoteOn { note, velocity, .. } => {
+                check_7bit("note", *note)?;
+                check_7bit("velocity", *velocity)
+            }
+            MidiMessage::PolyAftertouch { note, pressure, .. } => {
+                check_7bit("note", *note)?;
+                check_7bit("pressure", *pressure)
+            }
+            MidiMessage::ControlChange {
+                controller, value, ..
+            } => {
+                check_7bit("controller number", *controller)?;
+                check_7bit("MIDI value", *value)
+            }
+            MidiMessage::ProgramChange { program, .. } => check_7bit("program", *program),
+            MidiMessage::ChannelPressure { pressure, .. } => check_7bit("pressure", *pressure),
+            MidiMessage::PitchBend { value, .. } => check_14bit("pitch bend value", *value),
+            MidiMessage::SysEx(data) => match data.iter().position(|byte| *byte > 0x7F) {
+                Some(index) => Err(MidiMessageError::InvalidDataByte {
+                    position: index + 1,
+                    byte: data[index],
+                }),
+                None => Ok(()),
+            },
+            MidiMessage::MtcQuarterFrame(data) => check_7bit("quarter frame", *data),
+            MidiMessage::SongPosition(position) => check_14bit("song position", *position),
+            MidiMessage::SongSelect(song) => check_7bit("song number", *song),
+            _ => Ok(()),
+        }
+    }
+
+    /// Encodes the message into its wire bytes
+    pub fn to_bytes(&self) -> Result<Vec<u8>, MidiMessageError> {
+        self.validate()?;
+
+        let status = self.status();
+        let bytes = match self {
+            MidiMessage::NoteOff { note, velocity, .. }
+            | MidiMessage::NoteOn { note, velocity, .. } => {
+                vec![status, *note, *velocity]
+            }
+            MidiMessage::PolyAftertouch { note, pressure, .. } => vec![status, *note, *pressure],
+            MidiMessage::ControlChange {
+                controller, value, ..
+            } => vec![status, *controller, *value],
+            MidiMessage::ProgramChange { program, .. } => vec![status, *program],
+            MidiMessage::ChannelPressure { pressure, .. } => vec![status, *pressure],
+            MidiMessage::PitchBend { value, .. } | MidiMessage::SongPosition(value) => {
+                vec![status, (*value & 0x7F) as u8, (*value >> 7) as u8]
+            }
+            MidiMessage::SysEx(data) => {
+                let mut bytes = Vec::with_capacity(data.len() + 2);
+                bytes.push(0xF0);
+                bytes.extend_from_slice(data);
+                bytes.push(0xF7);
+                bytes
+            }
+            MidiMessage::MtcQuarterFrame(data) | MidiMessage::SongSelect(data) => {
+                vec![status, *data]
+            }
+            _ => vec![status],
+        };
+
+        Ok(bytes)
+    }
+
+    /// Parses exactly one complete message
+    /// Running status, missing bytes and trailing bytes are all rejected
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, MidiMessageError> {
+        let (&status, data) = bytes.split_first().ok_or(MidiMessageError::Empty)?;
+        if status < 0x80 {
+            return Err(MidiMessageError::MissingStatus(status));
+        }
+
+        if status == 0xF0 {
+            return parse_sysex(bytes);
+        }
+
+        let expected = message_length(status).ok_or(MidiMessageError::UndefinedStatus(status))?;
+        if bytes.len() < expected {
+            return Err(MidiMessageError::Truncated {
+                status,
+                expected,
+                actual: bytes.len(),
+            });
+        }
+        if bytes.len() > expected {
+            return Err(MidiMessageError::TrailingBytes {
+                status,
+                expected,
+                actual: bytes.len(),
+            });
+        }
+        if let Some(index) = data.iter().position(|byte| *byte > 0x7F) {
+            return Err(MidiMessageError::InvalidDataByte {
+                position: index + 1,
+                byte: data[index],
+            });
+        }
+
+        let channel = status & 0x0F;
+        let data1 = data.first().copied().unwrap_or(0);
+        let data2 = data.get(1).copied().unwrap_or(0);
+        let combined = u16::from(data1) | (u16::from(data2) << 7);
+
+        let message = match status {
+            0x80..=0x8F => MidiMessage::NoteOff {
+                channel,
+                note: data1,
+                velocity: data2,
+            },
+            0x90..=0x9F => MidiMessage::NoteOn {
+                channel,
+                note: data1,
+                velocity: data2,
+            },
+            0xA0..=0xAF => MidiMessage::PolyAftertouch {
+                channel,
+                note: data1,
+                pressure: data2,
+            },
+            0xB0..=0xBF => MidiMessage::ControlChange {
+                channel,
+                controller: data1,
+                value: data2,
+            },
+            0xC0..=0xCF => MidiMessage::ProgramChange {
+                channel,
+                program: data1,
+            },
+            0xD0..=0xDF => MidiMessage::ChannelPressure {
+                channel,
+                pressure: data1,
+            },
+            0xE0..=0xEF => MidiMessage::PitchBend {
+                channel,
+                value: combined,
+            },
+            0xF1 => MidiMessage::MtcQuarterFrame(data1),
+            0xF2 => MidiMessage::SongPosition(combined),
+            0xF3 => MidiMessage::SongSelect(data1),
+            0xF6 => MidiMessage::TuneRequest,
+            0xF8 => MidiMessage::TimingClock,
+            0xFA => MidiMessage::Start,
+            0xFB => MidiMessage::Continue,
+            0xFC => MidiMessage::Stop,
+            0xFE => MidiMessage::ActiveSensing,
+            0xFF => MidiMessage::SystemReset,
+            _ => return Err(MidiMessageError::UndefinedStatus(status)),
+        };
+
+        Ok(message)
+    }
+}
+
+fn parse_sysex(bytes: &[u8]) -> Result<MidiMessage, MidiMessageError> {
+    let end = bytes
+        .iter()
+        .position(|byte| *byte == 0xF7)
+        .ok_or(MidiMessageError::UnterminatedSysEx)?;
+
+    if let Some(index) = bytes[1..end].iter().position(|byte| *byte > 0x7F) {
+        return Err(MidiMessageError::InvalidDataByte {
+            position: index + 1,
+            byte: bytes[index + 1],
+        });
+    }
+    if end + 1 != bytes.len() {
+        return Err(MidiMessageError::TrailingBytes {
+            status: 0xF0,
+            expected: end + 1,
+            actual: bytes.len(),
+        });
+    }
+
+    Ok(MidiMessage::SysEx(bytes[1..end].to_vec()))
+}
+
+fn check_7bit(field: &'static str, value: u8) -> Result<(), MidiMessageError> {
+    if value > 0x7F {
+        return Err(MidiMessageError::ValueOutOfRange {
+            field,
+            value: u16::from(value),
+            max: 0x7F,
+        });
+    }
+    Ok(())
+}
+
+fn check_14bit(field: &'static str, value: u16) -> Result<(), MidiMessageError> {
+    if value > 0x3FFF {
+        return Err(MidiMessageError::ValueOutOfRange {
+            field,
+            value,
+            max: 0x3FFF,
+        });
+    }
+    Ok(())
+}
@@ -0,0 +1,372 @@
+use crate::{
+    MidiMessage, PianoffError, controller_name, display_channel, format_hex,
+    interpret_channel_mode_value,
+};
+use std::str::FromStr;
+
+/// Message categories the monitor can filter on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageType {
+    /// Note On and Note Off
+    Note,
+    PolyAftertouch,
+    /// Every Control Change, Channel Mode messages included
+    Cc,
+    /// Control Changes 120-127 only
+    ChannelMode,
+    Program,
+    ChannelPressure,
+    PitchBend,
+    Sysex,
+    /// MTC Quarter Frame, Song Position, Song Select and Tune Request
+    Common,
+    /// Timing Clock, Start, Continue, Stop, Active Sensing and System Reset
+    Realtime,
+}
+
+/// Names accepted by `MessageType::from_str`, in listing order
+pub const MESSAGE_TYPE_NAMES: &[&str] = &[
+    "note",
+    "poly-aftertouch",
+    "cc",
+    "channel-mode",
+    "program",
+    "channel-pressure",
+    "pitch-bend",
+    "sysex",
+    "common",
+    "realtime",
+];
+
+impl MessageType {
+    /// True when the message belongs to this category
+    pub fn matches(&self, message: &MidiMessage) -> bool {
+        match (self, message) {
+            (MessageType::Cc, MidiMessage::ControlChange { .. }) => true,
+            (MessageType::ChannelMode, MidiMessage::ControlChange { controller, .. }) => {
+                *controller >= 120
+            }
+            _ => *self == MessageType::of(message),
+        }
+    }
+
+    /// The category a message belongs to; Channel Mode messages count as `Cc`
+    pub fn of(message: &MidiMessage) -> Self {
+        match message {
+            MidiMessage::NoteOff { .. } | MidiMessage::NoteOn { .. } => MessageType::Note,
+            MidiMessage::PolyAftertouch { .. } => MessageType::PolyAftertouch,
+            MidiMessage::ControlChange { .. } => MessageType::Cc,
+            MidiMessage::ProgramChange { .. } => MessageType::Program,
+            MidiMessage::ChannelPressure { .. } => MessageType::ChannelPressure,
+            MidiMessage::PitchBend { .. } => MessageType::PitchBend,
+            MidiMessage::SysEx(_) => MessageType::Sysex,
+            MidiMessage::MtcQuarterFrame(_)
+            | MidiMessage::SongPosition(_)
+            | MidiMessage::SongSelect(_)
+            | MidiMessage::TuneRequest => MessageType::Common,
+            MidiMessage::TimingClock
+            | MidiMessage::Start
+            | MidiMessage::Continue
+            | MidiMessage::Stop
+            | MidiMessage::ActiveSensing
+            | MidiMessage::SystemReset => MessageType::Realtime,
+        }
+    }
+}
+
+impl FromStr for MessageType {
+    type Err = PianoffError;
+
+    /// Parses a category name such as `note` or `pitch-bend`, case-insensitively
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let message_type = match input.trim().to_lowercase().replace('_', "-").as_str() {
+            "note" | "notes" => MessageType::Note,
+            "poly-aftertouch" => MessageType::PolyAftertouch,
+            "cc" => MessageType::Cc,
+            "channel-mode" => MessageType::ChannelMode,
+            "program" => MessageType::Program,
+            "channel-pressure" => MessageType::ChannelPressure,
+            "pitch-bend" => MessageType::PitchBend,
+            "sysex" => MessageType::Sysex,
+            "common" => MessageType::Common,
+            "realtime" => MessageType::Realtime,
+            _ => {
+                return Err(PianoffError::Parse {
+                    field: "message type",
+                    input: input.trim().to_string(),
+                });
+            }
+        };
+        Ok(message_type)
+    }
+}
+
+/// Which incoming messages the monitor shows
+/// Realtime messages (clock, active sensing) are hidden unless asked for by type
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MonitorFilter {
+    /// Categories to show; empty shows everything except realtime messages
+    pub types: Vec<MessageType>,
+    /// Wire channels (0-15) to show; when set, system messages are hidden
+    pub channels: Vec<u8>,
+}
+
+impl MonitorFilter {
+    /// True when the message passes both the type and the channel filter
+    pub fn accepts(&self, message: &MidiMessage) -> bool {
+        let type_ok = if self.types.is_empty() {
+            MessageType::of(message) != MessageType::Realtime
+        } else {
+            self.types.iter().any(|t| t.matches(message))
+        };
+        let channel_ok = self.channels.is_empty()
+            || message
+                .channel()
+                .is_some_and(|channel| self.channels.contains(&channel));
+
+        type_ok && channel_ok
+    }
+
+    /// True when no filter is set, so undecodable messages are shown too
+    pub fn is_empty(&self) -> bool {
+        self.types.is_empty() && self.channels.is_empty()
+    }
+}
+
+/// Returns the note name in scientific pitch notation, where 60 is C4
+pub fn note_name(note: u8) -> String {
+    const NAMES: [&str; 12] = [
+        "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
+    ];
+    format!(
+        "{}{}",
+        NAMES[usize::from(note % 12)],
+        i16::from(note / 12) - 1
+    )
+}
+
+/// Describes a message as a type name and its values
+/// Channel Mode messages are named like `interpret_channel_mode_value`, e.g. "Local Control Off"
+pub fn describe_message(message: &MidiMessage) -> (&'static str, String) {
+    match message {
+        MidiMessage::NoteOff { note, velocity, .. } => (
+            "Note Off",
+            format!("note {} ({}) velocity {}", note, note_name(*note), velocity),
+        ),
+        MidiMessage::NoteOn { note, velocity, .. } => (
+            "Note On",
+            format!("note {} ({}) velocity {}", note, note_name(*note), velocity),
+        ),
+        MidiMessage::PolyAftertouch { note, pressure, .. } => (
+            "Poly Aftertouch",
+            format!("note {} ({}) pressure {}", note, note_name(*note), pressure),
+        ),
+        MidiMessage::ControlChange {
+            controller, value, ..
+        } => match interpret_channel_mode_value(*controller, *value) {
+            Some(meaning) => (
+                "Channel Mode",
+                format!("{} (CC #{} value {})", meaning, controller, value),
+            ),
+            None => match controller_name(*controller) {
+                Some(name) => (
+                    "Control Change",
+                    format!("#{} ({}) value {}", controller, name, value),
+                ),
+                None => ("Control Change", format!("#{} value {}", controller, value)),
+            },
+        },
+        MidiMessage::ProgramChange { program, .. } => ("Program Change", program.to_string()),
+        MidiMessage::ChannelPressure { pressure, .. } => ("Channel Pressure", pressure.to_string()),
+        MidiMessage::PitchBend { value, .. } => (
+            "Pitch Bend",
+            format!("{} ({:+})", value, i32::from(*value) - 8192),
+        ),
+        MidiMessage::SysEx(payload) => {
+            let framed: Vec<u8> = [0xF0]
+                .into_iter()
+                .chain(payload.iter().copied())
+                .chain([0xF7])
+                .collect();
+            (
+                "SysEx",
+                format!("{} ({} bytes)", format_hex(&framed), framed.len()),
+            )
+        }
+        MidiMessage::MtcQuarterFrame(data) => ("MTC Quarter Frame", format!("0x{:02X}", data)),
+        MidiMessage::SongPosition(position) => ("Song Position", position.to_string()),
+        MidiMessage::SongSelect(song) => ("Song Select", song.to_string()),
+        MidiMessage::TuneRequest => ("Tune Request", String::new()),
+        MidiMessage::TimingClock => ("Timing Clock", String::new()),
+        MidiMessage::Start => ("Start", String::new()),
+        MidiMessage::Continue => ("Continue", String::new()),
+        MidiMessage::Stop => ("Stop", String::new()),
+        MidiMessage::ActiveSensing => ("Active Sensing", String::new()),
+        MidiMessage::SystemReset => ("System Reset", String::new()),
+    }
+}
+
+/// Formats one incoming message for the monitor, or None when the filter hides it
+/// `elapsed_micros` is the time since monitoring started; `raw` prints hex bytes only
+pub fn format_monitor_line(
+    elapsed_micros: u64,
+    bytes: &[u8],
+    filter: &MonitorFilter,
+    raw: bool,
+) -> Option<String> {
+    let timestamp = format!("{:>10.3}", elapsed_micros as f64 / 1_000_000.0);
+
+    let message = match MidiMessage::from_bytes(bytes) {
+        Ok(message) => message,
+        Err(e) if filter.is_empty() => {
+            return Some(if raw {
+                format!("{}  {}", timestamp, format_hex(bytes))
+            } else {
+                format!(
+                    "{}  ch  -  {:<17} {} ({})",
+                    timestamp,
+                    "Invalid",
+                    format_hex(bytes),
+                    e
+                )
+            });
+        }
+        Err(_) => return None,
+    };
+
+    if !filter.accepts(&message) {
+        return None;
+    }
+
+    if raw {
+        return Some(format!("{}  {}", timestamp, format_hex(bytes)));
+    }
+
+    let channel = match message.channel() {
+        Some(channel) => format!("{:>2}", display_channel(channel)),
+        None => " -".to_string(),
+    };
+    let (kind, values) = describe_message(&message);
+    Some(
+        format!("{}  ch {}  {:<17} {}", timestamp, channel, kind, values)
+            .trim_end()
+            .to_string(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_note_names() {
+        assert_eq!(note_name(60), "C4");
+        assert_eq!(note_name(0), "C-1");
+        assert_eq!(note_name(61), "C#4");
+        assert_eq!(note_name(127), "G9");
+    }
+
+    #[test]
+    fn test_describe_messages() {
+        let cases = [
+            (vec![0x90, 60, 100], "Note On", "note 60 (C4) velocity 100"),
+            (
+                vec![0xB0, 64, 127],
+                "Control Change",
+                "#64 (sustain) value 127",
+            ),
+            (vec![0xB0, 3, 9], "Control Change", "#3 value 9"),
+            (
+                vec![0xB0, 122, 0],
+                "Channel Mode",
+                "Local Control Off (CC #122 value 0)",
+            ),
+            (
+                vec![0xB0, 123, 5],
+                "Channel Mode",
+                "Invalid All Notes Off Value 5 (CC #123 value 5)",
+            ),
+            (vec![0xE0, 0x00, 0x40], "Pitch Bend", "8192 (+0)"),
+            (vec![0xE0, 0x00, 0x00], "Pitch Bend", "0 (-8192)"),
+            (
+                vec![0xF0, 0x7E, 0x7F, 0x06, 0x01, 0xF7],
+                "SysEx",
+                "F0 7E 7F 06 01 F7 (6 bytes)",
+            ),
+            (vec![0xF0, 0xF7], "SysEx", "F0 F7 (2 bytes)"),
+            (vec![0xFE], "Active Sensing", ""),
+        ];
+
+        for (bytes, kind, values) in cases {
+            let message = MidiMessage::from_bytes(&bytes).unwrap();
+            assert_eq!(describe_message(&message), (kind, values.to_string()));
+        }
+    }
+
+    #[test]
+    fn test_monitor_line_format() {
+        let filter = MonitorFilter::default();
+        assert_eq!(
+            format_monitor_line(1_234_567, &[0x93, 60, 100], &filter, false).unwrap(),
+            "     1.235  ch  3  Note On           note 60 (C4) velocity 100"
+        );
+        assert_eq!(
+            format_monitor_line(
+                0,
+                &[0xFC],
+                &MonitorFilter {
+                    types: vec![MessageType::Realtime],
+                    channels: vec![],
+                },
+                false
+            )
+            .unwrap(),
+            "     0.000  ch  -  Stop"
+        );
+        assert_eq!(
+            format_monitor_line(0, &[0xB0, 122, 127], &filter, true).unwrap(),
+            "     0.000  B0 7A 7F"
+        );
+        assert!(
+            format_monitor_line(0, &[0x90, 0x80, 0], &filter, false)
+                .unwrap()
+                .contains("Invalid")
+        );
+    }
+
+    #[test]
+    fn test_monitor_filters() {
+        let note = MidiMessage::from_bytes(&[0x92, 60, 1]).unwrap();
+        let local = MidiMessage::from_bytes(&[0xB0, 122, 0]).unwrap();
+        let sustain = MidiMessage::from_bytes(&[0xB0, 64, 0]).unwrap();
+        let sensing = MidiMessage::ActiveSensing;
+
+        let all = MonitorFilter::default();
+        assert!(all.accepts(&note) && all.accepts(&local));
+        assert!(!all.accepts(&sensing));
+
+        let modes = MonitorFilter {
+            types: vec![MessageType::ChannelMode],
+            channels: vec![],
+        };
+        assert!(modes.accepts(&local) && !modes.accepts(&sustain) && !modes.accepts(&note));
+
+        let cc = MonitorFilter {
+            types: vec!["CC".parse().unwrap()],
+            channels: vec![],
+        };
+        assert!(cc.accepts(&local) && cc.accepts(&sustain));
+
+        let channel_2 = MonitorFilter {
+            types: vec![],
+            channels: vec![2],
+        };
+        assert!(channel_2.accepts(&note) && !channel_2.accepts(&local));
+
+        assert!(format_monitor_line(0, &[0x90, 0x80, 0], &channel_2, false).is_none());
+        assert!("kazoo".parse::<MessageType>().is_err());
+        for name in MESSAGE_TYPE_NAMES {
+            assert!(name.parse::<MessageType>().is_ok(), "{}", name);
+        }
+    }
+}
@@ -0,0 +1,145 @@
+use midi_cc_sender::{DeviceIdentity, PianoffError, find_port, request_identity};
+use midir::{
+    Ignore, MidiIO, MidiInput, MidiInputConnection, MidiInputPort, MidiOutput,
+    MidiOutputConnection, MidiOutputPort,
+};
+use std::sync::mpsc::{self, Receiver};
+use std::time::Duration;
+
+pub const CLIENT_NAME: &str = "MIDI CC Sender";
+
+/// An incoming message with its backend timestamp in microseconds
+pub type TimedMessage = (u64, Vec<u8>);
+
+/// Opens the MIDI output backend
+pub fn open_midi_output() -> Result<MidiOutput, PianoffError> {
+    MidiOutput::new(CLIENT_NAME).map_err(|e| PianoffError::Backend(e.to_string()))
+}
+
+/// Opens the MIDI input backend, receiving every message type including SysEx
+pub fn open_midi_input() -> Result<MidiInput, PianoffError> {
+    let mut midi_in =
+        MidiInput::new(CLIENT_NAME).map_err(|e| PianoffError::Backend(e.to_string()))?;
+    midi_in.ignore(Ignore::None);
+    Ok(midi_in)
+}
+
+/// Collects the display names of the given input or output ports
+pub fn port_names<T: MidiIO>(midi_io: &T, ports: &[T::Port]) -> Vec<String> {
+    ports
+        .iter()
+        .enumerate()
+        .map(|(i, port)| {
+            midi_io
+                .port_name(port)
+                .unwrap_or_else(|_| format!("Unknown Port {}", i))
+        })
+        .collect()
+}
+
+/// Resolves a port selector (index, name, substring or `/regex/`) without prompting
+/// Falls back to the only available port when no selector is given
+pub fn resolve_port(
+    midi_out: &MidiOutput,
+    selector: Option<&str>,
+) -> Result<(MidiOutputPort, String), PianoffError> {
+    let out_ports = midi_out.ports();
+    if out_ports.is_empty() {
+        return Err(PianoffError::NoPorts);
+    }
+
+    let names = port_names(midi_out, &out_ports);
+    let index = match selector {
+        Some(selector) => find_port(&names, selector)?,
+        None if out_ports.len() == 1 => 0,
+        None => return Err(PianoffError::PortRequired { available: names }),
+    };
+
+    Ok((out_ports[index].clone(), names[index].clone()))
+}
+
+/// Connects to an output port, naming the port in any error
+pub fn connect_output(
+    midi_out: MidiOutput,
+    port: &MidiOutputPort,
+    port_name: &str,
+) -> Result<MidiOutputConnection, PianoffError> {
+    midi_out
+        .connect(port, "midi-cc-sender")
+        .map_err(|e| PianoffError::Connect {
+            port: port_name.to_string(),
+            reason: e.to_string(),
+        })
+}
+
+/// Connects to the input port that belongs to the named output port
+/// Incoming messages are delivered on the returned channel; the connection must be
+/// kept alive for as long as messages are wanted
+pub fn connect_input(
+    output_name: &str,
+) -> Result<(MidiInputConnection<()>, Receiver<Vec<u8>>), PianoffError> {
+    let midi_in = open_midi_input()?;
+    let in_ports = midi_in.ports();
+    let names = port_names(&midi_in, &in_ports);
+
+    // Only an exact name will do; a looser match could listen to another device
+    let matches: Vec<usize> = (0..names.len())
+        .filter(|&i| names[i] == output_name)
+        .collect();
+    let index = match matches.as_slice() {
+        [index] => *index,
+        [] => {
+            return Err(PianoffError::NoInputPort {
+                port: output_name.to_string(),
+                available: names,
+            });
+        }
+        _ => {
+            return Err(PianoffError::AmbiguousPort {
+                selector: output_name.to_string(),
+                matches: matches.iter().map(|&i| names[i].clone()).collect(),
+            });
+        }
+    };
+
+    listen(midi_in, &in_ports[index], &names[index], |_, message| {
+        message.to_vec()
+    })
+}
+
+/// Connects to an input port and delivers each message, converted by `wrap`, on the
+/// returned channel; `wrap` gets the backend timestamp in microseconds and the bytes
+pub fn listen<T: Send + 'static>(
+    midi_in: MidiInput,
+    port: &MidiInputPort,
+    port_name: &str,
+    wrap: fn(u64, &[u8]) -> T,
+) -> Result<(MidiInputConnection<()>, Receiver<T>), PianoffError> {
+    let (sender, receiver) = mpsc::channel();
+    let connection = midi_in
+        .connect(
+            port,
+            "midi-cc-sender-in",
+            move |timestamp, message, _| {
+                let _ = sender.send(wrap(timestamp, message));
+            },
+            (),
+        )
+        .map_err(|e| PianoffError::Connect {
+            port: port_name.to_string(),
+            reason: e.to_string(),
+        })?;
+
+    Ok((connection, receiver))
+}
+
+/// Asks the device behind an output connection for its identity
+/// Listens on the input port of the same name until `timeout`
+pub fn identify_device(
+    connection: &mut MidiOutputConnection,
+    port_name: &str,
+    timeout: Duration,
+) -> Result<DeviceIdentity, PianoffError> {
+    let (_input, replies) = connect_input(port_name)?;
+    request_identity(connection, &replies, timeout)
+}
@@ -0,0 +1,425 @@
+use crate::{
+    DeviceIdentity, MidiMessage, MidiSink, PianoffError, check_range, format_hex,
+    interpret_local_control_value, parse_hex, send_midi_cc_122,
+};
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Deserializer};
+use std::fs;
+use std::path::Path;
+
+/// Profiles shipped with pianoff, checked after any user profiles
+const BUILTIN_PROFILES: &str = include_str!("profiles.toml");
+
+/// Identity Reply fields a profile matches on; a missing family or model matches any
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct IdentityPattern {
+    #[serde(deserialize_with = "hex_bytes")]
+    pub manufacturer: Vec<u8>,
+    #[serde(default)]
+    pub family: Option<u16>,
+    #[serde(default)]
+    pub model: Option<u16>,
+}
+
+impl IdentityPattern {
+    /// True when the identity has this manufacturer and, if given, family and model
+    pub fn matches(&self, identity: &DeviceIdentity) -> bool {
+        self.manufacturer == identity.manufacturer
+            && self.family.is_none_or(|family| family == identity.family)
+            && self.model.is_none_or(|model| model == identity.model)
+    }
+}
+
+/// How a device switches Local Control
+#[derive(Debug, Clone, PartialEq, Eq, Default, Deserialize)]
+#[serde(tag = "method", rename_all = "lowercase")]
+pub enum LocalControlMethod {
+    /// CC #122 on the receive channel
+    #[default]
+    Cc,
+    /// A fixed SysEx message for off and another for on
+    Sysex {
+        #[serde(deserialize_with = "hex_bytes")]
+        off: Vec<u8>,
+        #[serde(deserialize_with = "hex_bytes")]
+        on: Vec<u8>,
+    },
+    /// The device ignores Local Control messages
+    Unsupported,
+}
+
+impl LocalControlMethod {
+    /// Short description used in listings
+    pub fn describe(&self) -> &'static str {
+        match self {
+            LocalControlMethod::Cc => "CC #122",
+            LocalControlMethod::Sysex { .. } => "SysEx",
+            LocalControlMethod::Unsupported => "unsupported",
+        }
+    }
+}
+
+/// Everything pianoff knows about one device model
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DeviceProfile {
+    /// Friendly name shown next to the port
+    pub name: String,
+    /// Port name pattern, matched case-insensitively: substring or `/regex/`
+    #[serde(default)]
+    pub port: Option<String>,
+    /// Identity Reply the device answers with
+    #[serde(default)]
+    pub identity: Option<IdentityPattern>,
+    /// Channel the device listens on, as sent (0-15)
+    #[serde(default)]
+    pub channel: u8,
+    #[serde(default)]
+    pub local_control: LocalControlMethod,
+    /// Known oddities worth telling the user about
+    #[serde(default)]
+    pub quirks: Vec<String>,
+}
+
+/// Builds a `/regex/` port pattern, case-insensitive like the substring patterns
+fn port_regex(pattern: &str) -> Result<Regex, regex::Error> {
+    RegexBuilder::new(pattern).case_insensitive(true).build()
+}
+
+impl DeviceProfile {
+    /// True when the port name matches the profile's port pattern
+    pub fn matches_port(&self, port_name: &str) -> bool {
+        match self.port.as_deref().map(str::trim) {
+            Some(pattern) => match pattern
+                .strip_prefix('/')
+                .and_then(|rest| rest.strip_suffix('/'))
+            {
+                Some(regex) => port_regex(regex).is_ok_and(|regex| regex.is_match(port_name)),
+                None => port_name.to_lowercase().contains(&pattern.to_lowercase()),
+            },
+            None => false,
+        }
+    }
+
+    /// True when the identity matches the profile's identity pattern
+    pub fn matches_identity(&self, identity: &DeviceIdentity) -> bool {
+        self.identity
+            .as_ref()
+            .is_some_and(|pattern| pattern.matches(identity))
+    }
+
+    /// Checks that the profile can be matched and that its messages are well-formed
+    pub fn validate(&self) -> Result<(), String> {
+        if self.name.trim().is_empty() {
+            return Err("profile without a name".to_string());
+        }
+        if self.port.is_none() && self.identity.is_none() {
+            return Err(format!(
+                "'{}' needs a port pattern or an identity",
+                self.name
+            ));
+        }
+        if let Some(regex) = self
+            .port
+            .as_deref()
+            .and_then(|pattern| pattern.trim().strip_prefix('/'))
+            .and_then(|rest| rest.strip_suffix('/'))
+        {
+            port_regex(regex).map_err(|e| format!("'{}': {}", self.name, e))?;
+        }
+        check_range("MIDI channel", self.channel, 15)
+            .map_err(|e| format!("'{}': {}", self.name, e))?;
+
+        if let LocalControlMethod::Sysex { off, on } = &self.local_control {
+            for bytes in [off, on] {
+                match MidiMessage::from_bytes(bytes) {
+                    Ok(MidiMessage::SysEx(_)) => {}
+                    Ok(_) => {
+                        return Err(format!(
+                            "'{}': {} is not a SysEx message",
+                            self.name,
+                            format_hex(bytes)
+                        ));
+                    }
+                    Err(e) => return Err(format!("'{}': {}", self.name, e)),
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// An ordered list of device profiles; the first match wins
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProfileDb {
+    #[serde(default, rename = "profile")]
+    pub profiles: Vec<DeviceProfile>,
+}
+
+impl ProfileDb {
+    /// Profiles shipped with pianoff
+    pub fn builtin() -> Self {
+        ProfileDb::from_toml(BUILTIN_PROFILES, "built-in profiles")
+            .expect("built-in profiles are valid")
+    }
+
+    /// Parses profiles written as TOML `[[profile]]` tables
+    pub fn from_toml(text: &str, origin: &str) -> Result<Self, PianoffError> {
+        let db: ProfileDb = toml::from_str(text).map_err(|e| PianoffError::Profile {
+            origin: origin.to_string(),
+            reason: e.message().to_string(),
+        })?;
+        db.validated(origin)
+    }
+
+    /// Parses profiles written as JSON: `{ "profile": [ ... ] }`
+    pub fn from_json(text: &str, origin: &str) -> Result<Self, PianoffError> {
+        let db: ProfileDb = serde_json::from_str(text).map_err(|e| PianoffError::Profile {
+            origin: origin.to_string(),
+            reason: e.to_string(),
+        })?;
+        db.validated(origin)
+    }
+
+    /// Reads a profile file, as JSON when it ends in `.json` and as TOML otherwise
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, PianoffError> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path).map_err(|e| {
+            PianoffError::io(format!("Failed to read profiles '{}'", path.display()), e)
+        })?;
+        let origin = format!("'{}'", path.display());
+
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some(extension) if extension.eq_ignore_ascii_case("json") => {
+                ProfileDb::from_json(&text, &origin)
+            }
+            _ => ProfileDb::from_toml(&text, &origin),
+        }
+    }
+
+    fn validated(self, origin: &str) -> Result<Self, PianoffError> {
+        for profile in &self.profiles {
+            profile.validate().map_err(|reason| PianoffError::Profile {
+                origin: origin.to_string(),
+                reason,
+            })?;
+        }
+        Ok(self)
+    }
+
+    /// Puts `other`'s profiles ahead of these, so they win when both match
+    pub fn prepend(&mut self, other: ProfileDb) {
+        let mut profiles = other.profiles;
+        profiles.append(&mut self.profiles);
+        self.profiles = profiles;
+    }
+
+    /// Returns the first profile whose port pattern matches the port name
+    pub fn for_port(&self, port_name: &str) -> Option<&DeviceProfile> {
+        self.profiles
+            .iter()
+            .find(|profile| profile.matches_port(port_name))
+    }
+
+    /// Returns the first profile whose identity pattern matches
+    pub fn for_identity(&self, identity: &DeviceIdentity) -> Option<&DeviceProfile> {
+        self.profiles
+            .iter()
+            .find(|profile| profile.matches_identity(identity))
+    }
+}
+
+/// Switches Local Control the way a device profile prescribes
+/// `channel` is only used by CC #122; returns the confirmation text to display
+pub fn send_local_control<S: MidiSink + ?Sized>(
+    sink: &mut S,
+    profile: &DeviceProfile,
+    value: u8,
+    channel: u8,
+) -> Result<String, PianoffError> {
+    match &profile.local_control {
+        LocalControlMethod::Cc => send_midi_cc_122(sink, value, channel),
+        LocalControlMethod::Sysex { off, on } => {
+            let message = match value {
+                0 => off,
+                127 => on,
+                _ => {
+                    return Err(PianoffError::LocalControlUnsupported {
+                        device: profile.name.clone(),
+                        reason: format!(
+                            "its SysEx only turns it off (0) or on (127), not {}",
+                            value
+                        ),
+                    });
+                }
+            };
+
+            sink.send(message)?;
+
+            Ok(format!(
+                "✓ Successfully sent {} to {} via SysEx: {}",
+                interpret_local_control_value(value),
+                profile.name,
+                format_hex(message)
+            ))
+        }
+        LocalControlMethod::Unsupported => Err(PianoffError::LocalControlUnsupported {
+            device: profile.name.clone(),
+            reason: "it ignores Local Control messages".to_string(),
+        }),
+    }
+}
+
+fn hex_bytes<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+    let text = String::deserialize(deserializer)?;
+    parse_hex(&text).map_err(serde::de::Error::custom)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RecordingSink;
+
+    const SAMPLE: &str = r#"
+        [[profile]]
+        name = "Example Stage"
+        port = "/^Stage \\d+/"
+        identity = { manufacturer = "41", family = 0x0219 }
+        channel = 3
+        local_control = { method = "sysex", off = "F0 41 10 00 F7", on = "F0 41 10 7F F7" }
+        quirks = ["Needs a second to settle after power-on"]
+
+        [[profile]]
+        name = "Silent Box"
+        port = "silent"
+        local_control = { method = "unsupported" }
+    "#;
+
+    fn identity(manufacturer: &[u8], family: u16, model: u16) -> DeviceIdentity {
+        DeviceIdentity {
+            manufacturer: manufacturer.to_vec(),
+            family,
+            model,
+            version: [0; 4],
+        }
+    }
+
+    #[test]
+    fn test_builtin_profiles_are_valid() {
+        let db = ProfileDb::builtin();
+        assert!(!db.profiles.is_empty());
+        assert_eq!(
+            db.for_port("P-125").map(|profile| profile.name.as_str()),
+            Some("Yamaha P-125")
+        );
+        assert!(db.for_port("Digital Piano p125").is_some());
+    }
+
+    #[test]
+    fn test_profiles_from_toml_and_json() {
+        let db = ProfileDb::from_toml(SAMPLE, "sample").unwrap();
+        assert_eq!(db.profiles.len(), 2);
+        assert_eq!(db.profiles[0].channel, 3);
+        assert_eq!(
+            db.profiles[0].local_control,
+            LocalControlMethod::Sysex {
+                off: vec![0xF0, 0x41, 0x10, 0x00, 0xF7],
+                on: vec![0xF0, 0x41, 0x10, 0x7F, 0xF7],
+            }
+        );
+        assert_eq!(
+            db.profiles[1].local_control,
+            LocalControlMethod::Unsupported
+        );
+        assert_eq!(db.profiles[1].channel, 0);
+
+        let json = r#"{ "profile": [ { "name": "Json Piano", "port": "json" } ] }"#;
+        let db = ProfileDb::from_json(json, "sample.json").unwrap();
+        assert_eq!(db.profiles[0].local_control, LocalControlMethod::Cc);
+    }
+
+    #[test]
+    fn test_profiles_match_ports_and_identities() {
+        let db = ProfileDb::from_toml(SAMPLE, "sample").unwrap();
+
+        assert_eq!(db.for_port("Stage 2 MIDI 1").unwrap().name, "Example Stage");
+        assert!(db.for_port("Backstage 2").is_none());
+        assert_eq!(db.for_port("stage 2").unwrap().name, "Example Stage");
+        assert_eq!(db.for_port("My SILENT box").unwrap().name, "Silent Box");
+
+        assert_eq!(
+            db.for_identity(&identity(&[0x41], 0x0219, 7)).unwrap().name,
+            "Example Stage"
+        );
+        assert!(db.for_identity(&identity(&[0x41], 0x0100, 7)).is_none());
+        assert!(db.for_identity(&identity(&[0x43], 0x0219, 7)).is_none());
+    }
+
+    #[test]
+    fn test_prepended_profiles_take_precedence() {
+        let mut db = ProfileDb::builtin();
+        let user = r#"
+            [[profile]]
+            name = "My P-125"
+            port = "P-125"
+            channel = 1
+        "#;
+        db.prepend(ProfileDb::from_toml(user, "user").unwrap());
+
+        assert_eq!(db.for_port("Digital Piano P-125").unwrap().name, "My P-125");
+    }
+
+    #[test]
+    fn test_invalid_profiles_are_rejected() {
+        let cases = [
+            ("[[profile]]\nname = \"X\"\n", "port pattern or an identity"),
+            (
+                "[[profile]]\nname = \"X\"\nport = \"x\"\nchannel = 16\n",
+                "Must be 0-15",
+            ),
+            ("[[profile]]\nname = \"X\"\nport = \"/(/\"\n", "'X'"),
+            (
+                "[[profile]]\nname = \"X\"\nport = \"x\"\nlocal_control = { method = \"sysex\", off = \"F0 01\", on = \"F0 02 F7\" }\n",
+                "'X'",
+            ),
+            (
+                "[[profile]]\nname = \"X\"\nport = \"x\"\nlocal_control = { method = \"sysex\", off = \"B0 7A 00\", on = \"F0 02 F7\" }\n",
+                "not a SysEx message",
+            ),
+            (
+                "[[profile]]\nname = \"X\"\nport = \"x\"\ncolour = \"red\"\n",
+                "colour",
+            ),
+        ];
+
+        for (text, expected) in cases {
+            let error = ProfileDb::from_toml(text, "test").unwrap_err().to_string();
+            assert!(error.contains(expected), "'{}' lacks '{}'", error, expected);
+        }
+    }
+
+    #[test]
+    fn test_send_local_control_follows_profile_method() {
+        let db = ProfileDb::from_toml(SAMPLE, "sample").unwrap();
+        let mut sink = RecordingSink::new();
+
+        let confirmation = send_local_control(&mut sink, &db.profiles[0], 0, 3).unwrap();
+        assert_eq!(sink.messages, vec![vec![0xF0, 0x41, 0x10, 0x00, 0xF7]]);
+        assert_eq!(
+            confirmation,
+            "✓ Successfully sent Local Control Off to Example Stage via SysEx: F0 41 10 00 F7"
+        );
+
+        assert!(send_local_control(&mut sink, &db.profiles[0], 64, 3).is_err());
+        assert!(send_local_control(&mut sink, &db.profiles[1], 0, 0).is_err());
+        assert_eq!(sink.messages.len(), 1);
+
+        let builtin = ProfileDb::builtin();
+        let piano = builtin.for_port("P-125").unwrap();
+        send_local_control(&mut sink, piano, 127, piano.channel).unwrap();
+        assert_eq!(sink.messages[1], vec![0xB0, 122, 127]);
+    }
+}
@@ -0,0 +1,68 @@
+use crate::{MidiSink, PianoffError};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::time::Duration;
+
+/// How often the router checks whether it has been asked to stop
+pub const ROUTE_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Forwards every message from `input` to each output, in order, until `stop` is set
+/// or the input is closed; returns the number of messages forwarded
+pub fn route_messages(
+    input: &Receiver<Vec<u8>>,
+    outputs: &mut [&mut dyn MidiSink],
+    stop: &AtomicBool,
+) -> Result<u64, PianoffError> {
+    let mut forwarded = 0;
+    while !stop.load(Ordering::SeqCst) {
+        match input.recv_timeout(ROUTE_POLL_INTERVAL) {
+            Ok(message) => {
+                for output in outputs.iter_mut() {
+                    output.send(&message)?;
+                }
+                forwarded += 1;
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+    }
+    Ok(forwarded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RecordingSink;
+    use std::sync::mpsc;
+
+    #[test]
+    fn test_route_forwards_to_every_output() {
+        let (sender, receiver) = mpsc::channel();
+        sender.send(vec![0x90, 60, 100]).unwrap();
+        sender.send(vec![0x80, 60, 0]).unwrap();
+        drop(sender);
+
+        let mut daw = RecordingSink::new();
+        let mut piano = RecordingSink::new();
+        let stop = AtomicBool::new(false);
+        let forwarded = route_messages(&receiver, &mut [&mut daw, &mut piano], &stop).unwrap();
+
+        assert_eq!(forwarded, 2);
+        assert_eq!(daw.messages, vec![vec![0x90, 60, 100], vec![0x80, 60, 0]]);
+        assert_eq!(piano.messages, daw.messages);
+    }
+
+    #[test]
+    fn test_route_stops_when_asked() {
+        let (sender, receiver) = mpsc::channel();
+        sender.send(vec![0x90, 60, 100]).unwrap();
+
+        let mut output = RecordingSink::new();
+        let stop = AtomicBool::new(true);
+        let forwarded = route_messages(&receiver, &mut [&mut output], &stop).unwrap();
+
+        assert_eq!(forwarded, 0);
+        assert!(output.messages.is_empty());
+        drop(sender);
+    }
+}
@@ -0,0 +1,501 @@
+use crate::{
+    ChannelNumbering, MidiMessage, PianoffError, SysEx, ValidationMode, check_range,
+    controller_value_warning, parse_controller, validate_midi_value_with,
+};
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+/// One command of a script
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScriptStep {
+    /// `cc <controller> <value> [ch=<channel>]`; the controller may be a name
+    ControlChange {
+        controller: u8,
+        value: u8,
+        /// Channel as sent; None for the channel the script is run with
+        channel: Option<u8>,
+    },
+    /// `program <0-127> [ch=<channel>]`
+    ProgramChange { program: u8, channel: Option<u8> },
+    /// `sysex F0 ... F7`, written in any form `parse_hex` accepts
+    SysEx(SysEx),
+    /// `wait 50ms` or `wait 2s`; a bare number is in milliseconds
+    Wait(Duration),
+}
+
+impl ScriptStep {
+    /// The message to send, on `channel` unless the step names its own; None for waits
+    pub fn message(&self, channel: u8) -> Option<MidiMessage> {
+        match self {
+            ScriptStep::ControlChange {
+                controller,
+                value,
+                channel: own,
+            } => Some(MidiMessage::ControlChange {
+                channel: own.unwrap_or(channel),
+                controller: *controller,
+                value: *value,
+            }),
+            ScriptStep::ProgramChange {
+                program,
+                channel: own,
+            } => Some(MidiMessage::ProgramChange {
+                channel: own.unwrap_or(channel),
+                program: *program,
+            }),
+            ScriptStep::SysEx(sysex) => Some(sysex.clone().into()),
+            ScriptStep::Wait(_) => None,
+        }
+    }
+}
+
+/// A step with the line it was read from, counting from 1
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScriptLine {
+    pub line: usize,
+    pub step: ScriptStep,
+}
+
+/// A parsed script: one command per line, `#` starting a comment, e.g.
+///
+/// ```text
+/// numbering one-based
+/// cc local-control off ch=1
+/// wait 50ms
+/// sysex F0 41 10 42 12 40 00 7F 00 41 F7
+/// ```
+///
+/// `ch=` channels are 0-15 as sent unless a `numbering one-based` line before the
+/// first command makes them 1-16; `--one-based` does not change how a script reads
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Script {
+    pub steps: Vec<ScriptLine>,
+    /// Values that were accepted but are easy to misread, with their line and column
+    pub warnings: Vec<String>,
+}
+
+impl Script {
+    /// Parses a script; `origin` names it in errors
+    /// Values follow the same rules as on the command line, strictly
+    pub fn parse(text: &str, origin: &str) -> Result<Self, PianoffError> {
+        let mut script = Script::default();
+        let mut numbering = ChannelNumbering::ZeroBased;
+
+        for (index, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default();
+            let mut parser = LineParser {
+                origin,
+                line: index + 1,
+                text: line,
+                tokens: tokens(line),
+                header: script.steps.is_empty(),
+                numbering: &mut numbering,
+                warnings: &mut script.warnings,
+            };
+            if let Some(step) = parser.step()? {
+                script.steps.push(ScriptLine {
+                    line: index + 1,
+                    step,
+                });
+            }
+        }
+        Ok(script)
+    }
+
+    /// Reads and parses a script file
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, PianoffError> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path).map_err(|e| {
+            PianoffError::io(format!("Failed to read script '{}'", path.display()), e)
+        })?;
+        Script::parse(&text, &format!("'{}'", path.display()))
+    }
+}
+
+/// A word of a script line with its byte offset in the line
+type Token<'a> = (usize, &'a str);
+
+/// Splits a line at whitespace, keeping each word's byte offset
+fn tokens(line: &str) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    let mut start = None;
+    for (offset, c) in line.char_indices() {
+        match (c.is_whitespace(), start) {
+            (false, None) => start = Some(offset),
+            (true, Some(begin)) => {
+                tokens.push((begin, &line[begin..offset]));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(begin) = start {
+        tokens.push((begin, &line[begin..]));
+    }
+    tokens
+}
+
+struct LineParser<'a> {
+    origin: &'a str,
+    line: usize,
+    text: &'a str,
+    tokens: Vec<Token<'a>>,
+    /// No command has been read yet, so `numbering` may still be changed
+    header: bool,
+    numbering: &'a mut ChannelNumbering,
+    warnings: &'a mut Vec<String>,
+}
+
+impl<'a> LineParser<'a> {
+    /// Column of a byte offset, counting characters from 1
+    fn column(&self, offset: usize) -> usize {
+        self.text[..offset].chars().count() + 1
+    }
+
+    fn error(&self, offset: usize, reason: impl Into<String>) -> PianoffError {
+        PianoffError::Script {
+            origin: self.origin.to_string(),
+            line: self.line,
+            column: self.column(offset),
+            reason: reason.into(),
+        }
+    }
+
+    fn step(&mut self) -> Result<Option<ScriptStep>, PianoffError> {
+        let Some(&(offset, command)) = self.tokens.first() else {
+            return Ok(None);
+        };
+
+        let step = match command.to_lowercase().as_str() {
+            "cc" => {
+                let (arguments, channel) = self.arguments(2, true)?;
+                let (offset, controller) = arguments[0];
+                let controller =
+                    parse_controller(controller).map_err(|e| self.error(offset, e.to_string()))?;
+                ScriptStep::ControlChange {
+                    controller,
+                    value: self.value(arguments[1], controller)?,
+                    channel,
+                }
+            }
+            "program" => {
+                let (arguments, channel) = self.arguments(1, true)?;
+                let (offset, program) = arguments[0];
+                let program = program
+                    .parse::<u8>()
+                    .map_err(|_| PianoffError::Parse {
+                        field: "program number",
+                        input: program.to_string(),
+                    })
+                    .and_then(|program| {
+                        check_range("program number", program, 127)?;
+                        Ok(program)
+                    })
+                    .map_err(|e| self.error(offset, e.to_string()))?;
+                ScriptStep::ProgramChange { program, channel }
+            }
+            "sysex" => {
+                let Some(&(start, _)) = self.tokens.get(1) else {
+                    return Err(self.error(offset, "sysex needs the message bytes, F0 ... F7"));
+                };
+                let sysex = SysEx::from_hex(&self.text[start..])
+                    .map_err(|e| self.error(start, e.to_string()))?;
+                ScriptStep::SysEx(sysex)
+            }
+            "wait" => {
+                let (arguments, _) = self.arguments(1, false)?;
+                ScriptStep::Wait(self.duration(arguments[0])?)
+            }
+            "numbering" => {
+                let (arguments, _) = self.arguments(1, false)?;
+                let (offset, numbering) = arguments[0];
+                if !self.header {
+                    return Err(self.error(
+                        self.tokens[0].0,
+                        "numbering must come before the first command",
+                    ));
+                }
+                *self.numbering = match numbering.to_lowercase().as_str() {
+                    "zero-based" => ChannelNumbering::ZeroBased,
+                    "one-based" => ChannelNumbering::OneBased,
+                    _ => {
+                        return Err(self.error(
+                            offset,
+                            format!(
+                                "unknown numbering '{}'; expected zero-based or one-based",
+                                numbering
+                            ),
+                        ));
+                    }
+                };
+                return Ok(None);
+            }
+            _ => {
+                return Err(self.error(
+                    offset,
+                    format!(
+                        "unknown command '{}'; expected cc, program, sysex, wait or numbering",
+                        command
+                    ),
+                ));
+            }
+        };
+        Ok(Some(step))
+    }
+
+    /// Splits the words after the command into `count` arguments and a `ch=` option
+    fn arguments(
+        &self,
+        count: usize,
+        channel_allowed: bool,
+    ) -> Result<(Vec<Token<'a>>, Option<u8>), PianoffError> {
+        let mut arguments = Vec::new();
+        let mut channel = None;
+
+        for &(offset, token) in &self.tokens[1..] {
+            match token.split_once('=') {
+                Some(("ch", input)) if channel_allowed && channel.is_none() => {
+                    let value = input
+                        .parse::<u8>()
+                        .map_err(|_| PianoffError::Parse {
+                            field: "MIDI channel",
+                            input: input.to_string(),
+                        })
+                        .and_then(|number| self.numbering.from_display(number))
+                        .map_err(|e| self.error(offset + 3, e.to_string()))?;
+                    channel = Some(value);
+                }
+                Some(("ch", _)) if channel_allowed => {
+                    return Err(self.error(offset, "the channel is given more than once"));
+                }
+                Some((name, _)) => {
+                    return Err(self.error(offset, format!("unknown option '{}'", name)));
+                }
+                None if arguments.len() < count => arguments.push((offset, token)),
+                None => return Err(self.error(offset, format!("unexpected '{}'", token))),
+            }
+        }
+
+        if arguments.len() < count {
+            let (offset, command) = self.tokens[0];
+            return Err(self.error(
+                offset + command.len(),
+                format!(
+                    "{} needs {} argument{}",
+                    command,
+                    count,
+                    if count == 1 { "" } else { "s" }
+                ),
+            ));
+        }
+        Ok((arguments, channel))
+    }
+
+    /// Reads the value of a Control Change for `controller`
+    fn value(&mut self, (offset, input): Token<'_>, controller: u8) -> Result<u8, PianoffError> {
+        let (value, warning) = validate_midi_value_with(input, 0, ValidationMode::Strict)
+            .map_err(|e| self.error(offset, e.to_string()))?;
+        if let Some(warning) = controller_value_warning(warning, controller) {
+            let warning = warning.to_string();
+            self.warnings.push(format!(
+                "Warning (line {}, column {}): {}",
+                self.line,
+                self.column(offset),
+                warning.strip_prefix("Warning: ").unwrap_or(&warning)
+            ));
+        }
+        Ok(value)
+    }
+
+    /// Reads a duration such as `50ms` or `2s`; a bare number is in milliseconds
+    fn duration(&self, (offset, input): Token<'_>) -> Result<Duration, PianoffError> {
+        let (number, unit): (&str, fn(u64) -> Duration) = match input.strip_suffix("ms") {
+            Some(number) => (number, Duration::from_millis),
+            None => match input.strip_suffix('s') {
+                Some(number) => (number, Duration::from_secs),
+                None => (input, Duration::from_millis),
+            },
+        };
+        number.parse().map(unit).map_err(|_| {
+            self.error(
+                offset,
+                format!("invalid duration '{}'; write it like 50ms or 2s", input),
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCRIPT: &str = "\
+# Hand the piano over to the DAW
+cc local-control off ch=1
+  cc 121 0    # Reset All Controllers, on the default channel
+program 5 ch=2
+cc volume 100%
+
+wait 50ms
+sysex F0 41 10 42 12 40 00 7F 00 41 F7
+WAIT 1s
+";
+
+    #[test]
+    fn test_parse_script() {
+        let script = Script::parse(SCRIPT, "test").unwrap();
+        let steps: Vec<(usize, ScriptStep)> = script
+            .steps
+            .into_iter()
+            .map(|line| (line.line, line.step))
+            .collect();
+
+        assert_eq!(
+            steps,
+            vec![
+                (
+                    2,
+                    ScriptStep::ControlChange {
+                        controller: 122,
+                        value: 0,
+                        channel: Some(1)
+                    }
+                ),
+                (
+                    3,
+                    ScriptStep::ControlChange {
+                        controller: 121,
+                        value: 0,
+                        channel: None
+                    }
+                ),
+                (
+                    4,
+                    ScriptStep::ProgramChange {
+                        program: 5,
+                        channel: Some(2)
+                    }
+                ),
+                (
+                    5,
+                    ScriptStep::ControlChange {
+                        controller: 7,
+                        value: 127,
+                        channel: None
+                    }
+                ),
+                (7, ScriptStep::Wait(Duration::from_millis(50))),
+                (
+                    8,
+                    ScriptStep::SysEx(
+                        SysEx::from_payload(&[
+                            0x41, 0x10, 0x42, 0x12, 0x40, 0x00, 0x7F, 0x00, 0x41
+                        ])
+                        .unwrap()
+                    )
+                ),
+                (9, ScriptStep::Wait(Duration::from_secs(1))),
+            ]
+        );
+        assert!(script.warnings.is_empty());
+
+        assert_eq!(
+            steps[1].1.message(3),
+            Some(MidiMessage::ControlChange {
+                channel: 3,
+                controller: 121,
+                value: 0
+            })
+        );
+        assert_eq!(steps[4].1.message(3), None);
+    }
+
+    #[test]
+    fn test_script_errors_name_line_and_column() {
+        let cases = [
+            ("cc 122 200", "line 1, column 8: Invalid MIDI value: 200"),
+            (
+                "\ncc 122 0 ch=17",
+                "line 2, column 13: Invalid MIDI channel",
+            ),
+            ("cc pedal 0", "line 1, column 4: Unknown controller 'pedal'"),
+            ("  cc 122", "line 1, column 5: cc needs 2 arguments"),
+            ("cc 122 0 1", "line 1, column 10: unexpected '1'"),
+            (
+                "cc 122 0 ch=1 ch=2",
+                "line 1, column 15: the channel is given more than once",
+            ),
+            ("wait 50 ch=1", "line 1, column 9: unknown option 'ch'"),
+            ("wait soon", "line 1, column 6: invalid duration 'soon'"),
+            (
+                "program 128",
+                "line 1, column 9: Invalid program number: 128",
+            ),
+            ("sysex F0 43 80 F7", "line 1, column 7: "),
+            ("sysex F0 43 10", "line 1, column 7: "),
+            (
+                "sysex B0 7A 00",
+                "line 1, column 7: Expected a SysEx message starting with F0",
+            ),
+            ("sysex", "line 1, column 1: sysex needs the message bytes"),
+            ("note 60", "line 1, column 1: unknown command 'note'"),
+            (
+                "cc 122 0 ch=x",
+                "line 1, column 13: Invalid MIDI channel 'x'",
+            ),
+            (
+                "numbering one-based\ncc 122 0 ch=0",
+                "line 2, column 13: Invalid MIDI channel: 0. Must be 1-16.",
+            ),
+            (
+                "wait 1\nnumbering one-based",
+                "line 2, column 1: numbering must come before the first command",
+            ),
+            (
+                "numbering 1-16",
+                "line 1, column 11: unknown numbering '1-16'",
+            ),
+        ];
+
+        for (text, expected) in cases {
+            let error = Script::parse(text, "'setup.pf'").unwrap_err().to_string();
+            assert!(
+                error.starts_with("Invalid script 'setup.pf' at ") && error.contains(expected),
+                "{:?} does not mention {:?}",
+                error,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_script_numbering() {
+        let channel = |text: &str| match Script::parse(text, "test").unwrap().steps[0].step {
+            ScriptStep::ControlChange { channel, .. } => channel,
+            ref other => panic!("Unexpected step: {:?}", other),
+        };
+
+        assert_eq!(channel("cc 122 0 ch=1"), Some(1));
+        assert_eq!(channel("numbering zero-based\ncc 122 0 ch=15"), Some(15));
+        assert_eq!(
+            channel("# Channels as printed on the piano\nnumbering One-Based\n\ncc 122 0 ch=16"),
+            Some(15)
+        );
+    }
+
+    #[test]
+    fn test_script_warnings() {
+        let script = Script::parse("wait 10\ncc sustain 1\ncc volume 1", "test").unwrap();
+
+        assert_eq!(
+            script.steps[0].step,
+            ScriptStep::Wait(Duration::from_millis(10))
+        );
+        assert_eq!(script.warnings.len(), 1);
+        assert_eq!(
+            script.warnings[0],
+            "Warning (line 2, column 12): '1' is ambiguous; using value 1 (use 'on' or 127 for full on)."
+        );
+    }
+}
@@ -0,0 +1,976 @@
+use crate::{MidiSink, PianoffError, message_length};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Ticks per quarter note used unless another resolution is asked for
+pub const DEFAULT_PPQ: u16 = 480;
+/// Microseconds per quarter note at 120 BPM, the SMF default tempo
+pub const DEFAULT_TEMPO: u32 = 500_000;
+
+/// Meta event types written or understood by pianoff
+pub const META_TRACK_NAME: u8 = 0x03;
+pub const META_END_OF_TRACK: u8 = 0x2F;
+pub const META_TEMPO: u8 = 0x51;
+
+/// How often playing and recording check whether they have been asked to stop
+const STOP_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Appends a variable-length quantity: 7 bits per byte, most significant first,
+/// with the top bit set on every byte but the last
+/// Values above 0x0FFFFFFF do not fit in the four bytes SMF allows and are clamped
+pub fn write_vlq(value: u32, out: &mut Vec<u8>) {
+    let value = value.min(0x0FFF_FFFF);
+    let mut shift = 21;
+    while shift > 0 && value >> shift == 0 {
+        shift -= 7;
+    }
+    while shift > 0 {
+        out.push(0x80 | (value >> shift & 0x7F) as u8);
+        shift -= 7;
+    }
+    out.push((value & 0x7F) as u8);
+}
+
+/// Builds a format 0 Standard MIDI File from messages stamped with the time they were
+/// sent; delta times are derived from those timestamps at a fixed tempo
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SmfWriter {
+    ppq: u16,
+    tempo: u32,
+    track_name: Option<String>,
+    /// Microseconds since the start, with the raw message
+    events: Vec<(u64, Vec<u8>)>,
+}
+
+impl SmfWriter {
+    /// Starts an empty file with `ppq` ticks per quarter note (1-32767) and `tempo`
+    /// microseconds per quarter note (1-16777215)
+    pub fn new(ppq: u16, tempo: u32) -> Result<Self, PianoffError> {
+        if !(1..=0x7FFF).contains(&ppq) {
+            return Err(PianoffError::OutOfRange {
+                field: "PPQ",
+                value: u32::from(ppq),
+                min: 1,
+                max: 0x7FFF,
+            });
+        }
+        if !(1..=0xFF_FFFF).contains(&tempo) {
+            return Err(PianoffError::OutOfRange {
+                field: "tempo (microseconds per quarter note)",
+                value: tempo,
+                min: 1,
+                max: 0xFF_FFFF,
+            });
+        }
+
+        Ok(SmfWriter {
+            ppq,
+            tempo,
+            track_name: None,
+            events: Vec::new(),
+        })
+    }
+
+    /// Names the track with a Sequence/Track Name meta event
+    pub fn track_name(mut self, name: &str) -> Self {
+        self.track_name = Some(name.to_string());
+        self
+    }
+
+    /// Adds a message sent `micros` microseconds after the start
+    /// A timestamp earlier than the previous one is moved up to it
+    pub fn push(&mut self, micros: u64, message: &[u8]) {
+        let micros = self
+            .events
+            .last()
+            .map_or(micros, |(last, _)| micros.max(*last));
+        self.events.push((micros, message.to_vec()));
+    }
+
+    /// Number of messages added so far
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    /// True when no message was added
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    /// Converts a time since the start to ticks, rounding to the nearest tick
+    pub fn ticks(&self, micros: u64) -> u64 {
+        let ticks = (u128::from(micros) * u128::from(self.ppq) * 2 + u128::from(self.tempo))
+            / (u128::from(self.tempo) * 2);
+        ticks as u64
+    }
+
+    /// Serializes the header chunk and the single track chunk
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut track = Vec::new();
+
+        if let Some(name) = &self.track_name {
+            write_meta(&mut track, 0, META_TRACK_NAME, name.as_bytes());
+        }
+        write_meta(&mut track, 0, META_TEMPO, &self.tempo.to_be_bytes()[1..]);
+
+        // Deltas come from rounded absolute times, so rounding never accumulates
+        let mut previous = 0;
+        for (micros, message) in &self.events {
+            let ticks = self.ticks(*micros);
+            let delta = u32::try_from(ticks - previous).unwrap_or(u32::MAX);
+            previous = ticks;
+            write_vlq(delta, &mut track);
+            write_event(&mut track, message);
+        }
+        write_meta(&mut track, 0, META_END_OF_TRACK, &[]);
+
+        let mut bytes = Vec::with_capacity(22 + track.len());
+        bytes.extend_from_slice(b"MThd");
+        bytes.extend_from_slice(&6u32.to_be_bytes());
+        bytes.extend_from_slice(&0u16.to_be_bytes());
+        bytes.extend_from_slice(&1u16.to_be_bytes());
+        bytes.extend_from_slice(&self.ppq.to_be_bytes());
+        bytes.extend_from_slice(b"MTrk");
+        bytes.extend_from_slice(&(track.len() as u32).to_be_bytes());
+        bytes.extend_from_slice(&track);
+        bytes
+    }
+
+    /// Writes the file to disk
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), PianoffError> {
+        let path = path.as_ref();
+        fs::write(path, self.to_bytes()).map_err(|e| {
+            PianoffError::io(format!("Failed to write MIDI file '{}'", path.display()), e)
+        })
+    }
+}
+
+fn write_meta(track: &mut Vec<u8>, delta: u32, kind: u8, data: &[u8]) {
+    write_vlq(delta, track);
+    track.extend_from_slice(&[0xFF, kind]);
+    write_vlq(data.len() as u32, track);
+    track.extend_from_slice(data);
+}
+
+/// Writes one message as a track event: channel messages as they are, SysEx as
+/// `F0 <length> <data> F7`, and any other system message escaped as `F7 <length> <bytes>`
+fn write_event(track: &mut Vec<u8>, message: &[u8]) {
+    match message.first() {
+        Some(0x80..=0xEF) => track.extend_from_slice(message),
+        Some(0xF0) => {
+            track.push(0xF0);
+            write_vlq(message.len() as u32 - 1, track);
+            track.extend_from_slice(&message[1..]);
+        }
+        _ => {
+            track.push(0xF7);
+            write_vlq(message.len() as u32, track);
+            track.extend_from_slice(message);
+        }
+    }
+}
+
+/// Sink that passes every message on and also keeps it, timed from the first one,
+/// for saving as a Standard MIDI File
+pub struct SmfRecorder<S: MidiSink> {
+    sink: S,
+    start: Option<Instant>,
+    smf: SmfWriter,
+}
+
+impl<S: MidiSink> SmfRecorder<S> {
+    /// Records into `smf` whatever is sent to `sink`
+    pub fn new(sink: S, smf: SmfWriter) -> Self {
+        SmfRecorder {
+            sink,
+            start: None,
+            smf,
+        }
+    }
+
+    /// The messages recorded so far
+    pub fn smf(&self) -> &SmfWriter {
+        &self.smf
+    }
+}
+
+impl<S: MidiSink> MidiSink for SmfRecorder<S> {
+    fn send(&mut self, message: &[u8]) -> Result<(), PianoffError> {
+        self.sink.send(message)?;
+        let start = *self.start.get_or_insert_with(Instant::now);
+        self.smf.push(start.elapsed().as_micros() as u64, message);
+        Ok(())
+    }
+}
+
+/// How a file's delta times translate to real time, from its header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Division {
+    /// Ticks per quarter note; real time follows the file's tempo map
+    Metrical(u16),
+    /// SMPTE frames per second and ticks per frame; tempo changes do not apply
+    Timecode { fps: u8, ticks_per_frame: u8 },
+}
+
+/// One event of a track
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SmfEvent {
+    /// Bytes to send as they are: a channel message, a SysEx message including its
+    /// F0, or the contents of an F7 escape
+    Message(Vec<u8>),
+    /// Set Tempo, in microseconds per quarter note
+    Tempo(u32),
+    /// Any other meta event; End of Track is not kept
+    Meta { kind: u8, data: Vec<u8> },
+}
+
+/// A track event at its absolute position in ticks
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrackEvent {
+    pub tick: u64,
+    pub event: SmfEvent,
+}
+
+/// A parsed Standard MIDI File of format 0 or 1
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Smf {
+    pub format: u16,
+    pub division: Division,
+    pub tracks: Vec<Vec<TrackEvent>>,
+}
+
+impl Smf {
+    /// Parses the bytes of a file; `origin` names it in errors
+    /// Chunks other than MThd and MTrk are skipped, as the format requires
+    pub fn from_bytes(bytes: &[u8], origin: &str) -> Result<Self, PianoffError> {
+        let mut reader = Reader {
+            bytes,
+            position: 0,
+            end: bytes.len(),
+            origin,
+        };
+
+        if reader.take(4)? != b"MThd" {
+            return Err(reader.error_at(0, "not a Standard MIDI File (no MThd header)"));
+        }
+        let header_length = reader.u32()? as usize;
+        if header_length < 6 {
+            return Err(reader.error(format!(
+                "header is {} bytes long, expected 6",
+                header_length
+            )));
+        }
+        let format = reader.u16()?;
+        let track_count = reader.u16()?;
+        let division = match reader.u16()? {
+            division if division & 0x8000 == 0 && division > 0 => Division::Metrical(division),
+            division if division & 0x8000 != 0 => Division::Timecode {
+                // The frame rate is stored negated, in two's complement
+                fps: ((division >> 8) as u8 as i8).unsigned_abs(),
+                ticks_per_frame: division as u8,
+            },
+            _ => return Err(reader.error("division of 0 ticks per quarter note")),
+        };
+        reader.skip(header_length - 6)?;
+
+        match (format, track_count) {
+            (0, 1) | (1, _) => {}
+            (0, count) => {
+                return Err(reader.error(format!(
+                    "format 0 must have exactly one track, the header announces {}",
+                    count
+                )));
+            }
+            (format, _) => {
+                return Err(reader.error(format!(
+                    "format {} is not supported, only formats 0 and 1",
+                    format
+                )));
+            }
+        }
+        if let Division::Timecode {
+            fps,
+            ticks_per_frame,
+        } = division
+            && (![24, 25, 29, 30].contains(&fps) || ticks_per_frame == 0)
+        {
+            return Err(reader.error_at(
+                12,
+                format!(
+                    "invalid SMPTE division of {} frames per second, {} ticks per frame",
+                    fps, ticks_per_frame
+                ),
+            ));
+        }
+
+        let mut tracks = Vec::new();
+        while tracks.len() < usize::from(track_count) {
+            if reader.position == reader.end {
+                return Err(reader.error(format!(
+                    "the header announces {} tracks, the file has {}",
+                    track_count,
+                    tracks.len()
+                )));
+            }
+            let chunk = reader.position;
+            let id = reader.take(4)?;
+            let length = reader.u32()? as usize;
+            if reader.end - reader.position < length {
+                return Err(reader.error_at(
+                    chunk,
+                    format!("chunk of {} bytes runs past the end of the file", length),
+                ));
+            }
+            if id == b"MTrk" {
+                let mut track = Reader {
+                    end: reader.position + length,
+                    ..reader
+                };
+                tracks.push(track.track()?);
+            }
+            reader.skip(length)?;
+        }
+
+        Ok(Smf {
+            format,
+            division,
+            tracks,
+        })
+    }
+
+    /// Reads and parses a file
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, PianoffError> {
+        let path = path.as_ref();
+        let bytes = fs::read(path).map_err(|e| {
+            PianoffError::io(format!("Failed to read MIDI file '{}'", path.display()), e)
+        })?;
+        Smf::from_bytes(&bytes, &format!("'{}'", path.display()))
+    }
+
+    /// Every message to send, from all tracks, with the time to send it in
+    /// microseconds from the start; tempo changes in any track apply to all of them
+    pub fn timed_messages(&self) -> Vec<(u64, Vec<u8>)> {
+        // A stable sort keeps simultaneous events in track order
+        let mut events: Vec<&TrackEvent> = self.tracks.iter().flatten().collect();
+        events.sort_by_key(|event| event.tick);
+
+        // Times are computed from the last tempo change, so rounding never accumulates
+        let (mut base_tick, mut base_micros, mut tempo) = (0, 0, u64::from(DEFAULT_TEMPO));
+        let micros = |tick: u64, base_tick: u64, base_micros: u64, tempo: u64| {
+            let elapsed = u128::from(tick - base_tick);
+            let micros = match self.division {
+                Division::Metrical(ppq) => elapsed * u128::from(tempo) / u128::from(ppq),
+                Division::Timecode {
+                    fps,
+                    ticks_per_frame,
+                } => {
+                    // 29 stands for the 29.97 frames per second of drop-frame timecode
+                    let (frames, seconds) = match fps {
+                        29 => (30_000, 1001),
+                        fps => (u128::from(fps), 1),
+                    };
+                    elapsed * 1_000_000 * seconds / (frames * u128::from(ticks_per_frame))
+                }
+            };
+            base_micros + micros as u64
+        };
+
+        let mut messages = Vec::new();
+        for event in events {
+            match &event.event {
+                SmfEvent::Message(bytes) => messages.push((
+                    micros(event.tick, base_tick, base_micros, tempo),
+                    bytes.clone(),
+                )),
+                SmfEvent::Tempo(new_tempo) => {
+                    base_micros = micros(event.tick, base_tick, base_micros, tempo);
+                    base_tick = event.tick;
+                    tempo = u64::from(*new_tempo);
+                }
+                SmfEvent::Meta { .. } => {}
+            }
+        }
+        messages
+    }
+
+    /// Channels, as sent, that the file's channel messages use
+    pub fn channels(&self) -> Vec<u8> {
+        let channels: BTreeSet<u8> = self
+            .tracks
+            .iter()
+            .flatten()
+            .filter_map(|event| match &event.event {
+                SmfEvent::Message(bytes) => match bytes.first() {
+                    Some(status @ 0x80..=0xEF) => Some(status & 0x0F),
+                    _ => None,
+                },
+                _ => None,
+            })
+            .collect();
+        channels.into_iter().collect()
+    }
+}
+
+/// Reads the big-endian fields of a file, knowing where it is for error messages
+#[derive(Clone, Copy)]
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+    /// End of the current chunk
+    end: usize,
+    origin: &'a str,
+}
+
+impl<'a> Reader<'a> {
+    fn error(&self, reason: impl Into<String>) -> PianoffError {
+        self.error_at(self.position, reason)
+    }
+
+    fn error_at(&self, offset: usize, reason: impl Into<String>) -> PianoffError {
+        PianoffError::Smf {
+            origin: self.origin.to_string(),
+            offset,
+            reason: reason.into(),
+        }
+    }
+
+    fn take(&mut self, count: usize) -> Result<&'a [u8], PianoffError> {
+        if self.end - self.position < count {
+            return Err(self.error("unexpected end of data"));
+        }
+        let bytes = &self.bytes[self.position..self.position + count];
+        self.position += count;
+        Ok(bytes)
+    }
+
+    fn skip(&mut self, count: usize) -> Result<(), PianoffError> {
+        self.take(count).map(|_| ())
+    }
+
+    fn byte(&mut self) -> Result<u8, PianoffError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, PianoffError> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, PianoffError> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    /// Reads a variable-length quantity of at most four bytes
+    fn vlq(&mut self) -> Result<u32, PianoffError> {
+        let start = self.position;
+        let mut value = 0;
+        for _ in 0..4 {
+            let byte = self.byte()?;
+            value = value << 7 | u32::from(byte & 0x7F);
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(self.error_at(start, "variable-length quantity longer than 4 bytes"))
+    }
+
+    /// Reads the events of a track chunk up to End of Track or the end of the chunk
+    fn track(&mut self) -> Result<Vec<TrackEvent>, PianoffError> {
+        let mut events = Vec::new();
+        let mut tick = 0;
+        let mut running_status = None;
+
+        while self.position < self.end {
+            tick += u64::from(self.vlq()?);
+            let start = self.position;
+            let event = match self.byte()? {
+                0xFF => {
+                    running_status = None;
+                    let kind = self.byte()?;
+                    let length = self.vlq()? as usize;
+                    let data = self.take(length)?;
+                    match kind {
+                        META_END_OF_TRACK => break,
+                        META_TEMPO if length != 3 => {
+                            return Err(self.error_at(
+                                start,
+                                format!("Set Tempo with {} data bytes instead of 3", length),
+                            ));
+                        }
+                        META_TEMPO => {
+                            SmfEvent::Tempo(u32::from_be_bytes([0, data[0], data[1], data[2]]))
+                        }
+                        kind => SmfEvent::Meta {
+                            kind,
+                            data: data.to_vec(),
+                        },
+                    }
+                }
+                status @ (0xF0 | 0xF7) => {
+                    running_status = None;
+                    let length = self.vlq()? as usize;
+                    let data = self.take(length)?;
+                    let mut message = Vec::with_capacity(length + 1);
+                    if status == 0xF0 {
+                        message.push(0xF0);
+                    }
+                    message.extend_from_slice(data);
+                    SmfEvent::Message(message)
+                }
+                status @ 0x80..=0xEF => {
+                    running_status = Some(status);
+                    self.channel_message(status, start)?
+                }
+                data @ 0x00..=0x7F => {
+                    let status = running_status.ok_or_else(|| {
+                        self.error_at(
+                            start,
+                            format!("data byte {:02X} without a status byte", data),
+                        )
+                    })?;
+                    self.position -= 1;
+                    self.channel_message(status, start)?
+                }
+                status => {
+                    return Err(self.error_at(
+                        start,
+                        format!("status byte {:02X} is not allowed in a track", status),
+                    ));
+                }
+            };
+            events.push(TrackEvent { tick, event });
+        }
+        Ok(events)
+    }
+
+    /// Reads the data bytes of a channel message whose status was already read
+    fn channel_message(&mut self, status: u8, start: usize) -> Result<SmfEvent, PianoffError> {
+        let length = message_length(status).unwrap_or(1);
+        let mut message = Vec::with_capacity(length);
+        message.push(status);
+        for _ in 1..length {
+            match self.byte()? {
+                data @ 0x00..=0x7F => message.push(data),
+                byte => {
+                    return Err(self.error_at(
+                        start,
+                        format!("status byte {:02X} inside a {:02X} message", byte, status),
+                    ));
+                }
+            }
+        }
+        Ok(SmfEvent::Message(message))
+    }
+}
+
+/// Sends timed messages to a sink, each when its time comes, until the last one is
+/// sent or `stop` is set; returns the number of messages sent
+pub fn play_messages<S: MidiSink + ?Sized>(
+    sink: &mut S,
+    messages: &[(u64, Vec<u8>)],
+    stop: &AtomicBool,
+) -> Result<usize, PianoffError> {
+    let start = Instant::now();
+    for (sent, (micros, message)) in messages.iter().enumerate() {
+        let due = start + Duration::from_micros(*micros);
+        loop {
+            if stop.load(Ordering::SeqCst) {
+                return Ok(sent);
+            }
+            let now = Instant::now();
+            if now >= due {
+                break;
+            }
+            thread::sleep((due - now).min(STOP_POLL_INTERVAL));
+        }
+        sink.send(message)?;
+    }
+    Ok(messages.len())
+}
+
+/// Adds the messages arriving on `input`, each with a timestamp in microseconds, to
+/// `smf` until `stop` is set or the input is closed; returns the number added
+/// The file starts at `start`, placing the first message at the time it arrived, or
+/// at the first message when there is no `start`; later ones follow their timestamps
+/// Realtime messages (clock, active sensing) are left out
+pub fn record_messages(
+    input: &Receiver<(u64, Vec<u8>)>,
+    smf: &mut SmfWriter,
+    start: Option<Instant>,
+    stop: &AtomicBool,
+) -> usize {
+    let mut first = None;
+    let mut recorded = 0;
+    while !stop.load(Ordering::SeqCst) {
+        match input.recv_timeout(STOP_POLL_INTERVAL) {
+            Ok((_, message)) if matches!(message.first(), None | Some(0xF8..=0xFF)) => {}
+            Ok((timestamp, message)) => {
+                let (first_timestamp, lead_in) = *first.get_or_insert_with(|| {
+                    let lead_in = start.map_or(0, |start| start.elapsed().as_micros() as u64);
+                    (timestamp, lead_in)
+                });
+                smf.push(
+                    lead_in + timestamp.saturating_sub(first_timestamp),
+                    &message,
+                );
+                recorded += 1;
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+    }
+    recorded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RecordingSink;
+    use std::sync::mpsc;
+
+    fn vlq(value: u32) -> Vec<u8> {
+        let mut out = Vec::new();
+        write_vlq(value, &mut out);
+        out
+    }
+
+    #[test]
+    fn test_variable_length_quantities() {
+        let cases: [(u32, &[u8]); 9] = [
+            (0x00, &[0x00]),
+            (0x40, &[0x40]),
+            (0x7F, &[0x7F]),
+            (0x80, &[0x81, 0x00]),
+            (0x2000, &[0xC0, 0x00]),
+            (0x3FFF, &[0xFF, 0x7F]),
+            (0x4000, &[0x81, 0x80, 0x00]),
+            (0x10_0000, &[0xC0, 0x80, 0x00]),
+            (0x0FFF_FFFF, &[0xFF, 0xFF, 0xFF, 0x7F]),
+        ];
+
+        for (value, expected) in cases {
+            assert_eq!(vlq(value), expected, "VLQ of {:#X}", value);
+        }
+        assert_eq!(vlq(u32::MAX), vec![0xFF, 0xFF, 0xFF, 0x7F]);
+    }
+
+    #[test]
+    fn test_empty_file() {
+        let smf = SmfWriter::new(480, DEFAULT_TEMPO).unwrap();
+        assert_eq!(
+            smf.to_bytes(),
+            vec![
+                b'M', b'T', b'h', b'd', 0, 0, 0, 6, 0, 0, 0, 1, 0x01, 0xE0, //
+                b'M', b'T', b'r', b'k', 0, 0, 0, 11, //
+                0x00, 0xFF, 0x51, 0x03, 0x07, 0xA1, 0x20, //
+                0x00, 0xFF, 0x2F, 0x00,
+            ]
+        );
+
+        assert!(SmfWriter::new(0, DEFAULT_TEMPO).is_err());
+        assert!(SmfWriter::new(0x8000, DEFAULT_TEMPO).is_err());
+        assert!(SmfWriter::new(480, 0x100_0000).is_err());
+    }
+
+    #[test]
+    fn test_events_and_delta_times() {
+        let mut smf = SmfWriter::new(96, DEFAULT_TEMPO).unwrap().track_name("pf");
+        smf.push(0, &[0xB0, 0x7A, 0x00]);
+        smf.push(500_000, &[0xF0, 0x43, 0x10, 0xF7]);
+        smf.push(1_502_604, &[0xF8]);
+        smf.push(1_000_000, &[0xB0, 0x7A, 0x7F]);
+
+        let track: &[u8] = &[
+            0x00, 0xFF, 0x03, 0x02, b'p', b'f', //
+            0x00, 0xFF, 0x51, 0x03, 0x07, 0xA1, 0x20, //
+            0x00, 0xB0, 0x7A, 0x00, //
+            0x60, 0xF0, 0x03, 0x43, 0x10, 0xF7, //
+            0x81, 0x40, 0xF7, 0x01, 0xF8, //
+            0x00, 0xB0, 0x7A, 0x7F, //
+            0x00, 0xFF, 0x2F, 0x00,
+        ];
+        let bytes = smf.to_bytes();
+        assert_eq!(
+            &bytes[14..22],
+            &[b'M', b'T', b'r', b'k', 0, 0, 0, track.len() as u8]
+        );
+        assert_eq!(&bytes[22..], track);
+        assert_eq!(smf.ticks(1_502_604), 288);
+    }
+
+    #[test]
+    fn test_recorder_passes_messages_on() {
+        let mut sink = RecordingSink::new();
+        let mut recorder = SmfRecorder::new(&mut sink, SmfWriter::new(480, DEFAULT_TEMPO).unwrap());
+
+        recorder.send(&[0xB0, 0x7A, 0x00]).unwrap();
+        recorder.send(&[0xB0, 0x7B, 0x00]).unwrap();
+
+        assert_eq!(recorder.smf().len(), 2);
+        drop(recorder);
+        assert_eq!(
+            sink.messages,
+            vec![vec![0xB0, 0x7A, 0x00], vec![0xB0, 0x7B, 0x00]]
+        );
+    }
+
+    /// Builds a file from a header and the contents of its track chunks
+    fn file(format: u16, division: u16, tracks: &[&[u8]]) -> Vec<u8> {
+        let mut bytes = b"MThd\0\0\0\x06".to_vec();
+        bytes.extend_from_slice(&format.to_be_bytes());
+        bytes.extend_from_slice(&(tracks.len() as u16).to_be_bytes());
+        bytes.extend_from_slice(&division.to_be_bytes());
+        for track in tracks {
+            bytes.extend_from_slice(b"MTrk");
+            bytes.extend_from_slice(&(track.len() as u32).to_be_bytes());
+            bytes.extend_from_slice(track);
+        }
+        bytes
+    }
+
+    #[test]
+    fn test_parse_format_0() {
+        let bytes = file(
+            0,
+            96,
+            &[&[
+                0x00, 0xFF, 0x03, 0x04, b's', b'o', b'n', b'g', //
+                0x00, 0xF0, 0x05, 0x7E, 0x7F, 0x09, 0x01, 0xF7, //
+                0x00, 0x90, 0x3C, 0x64, //
+                0x00, 0x40, 0x64, // running status
+                0x60, 0x3C, 0x00, //
+                0x00, 0xFF, 0x51, 0x03, 0x0F, 0x42, 0x40, // 60 BPM
+                0x30, 0xC1, 0x05, //
+                0x00, 0xFF, 0x45, 0x00, // unknown meta type
+                0x81, 0x40, 0x80, 0x40, 0x00, //
+                0x00, 0xF7, 0x01, 0xF8, //
+                0x00, 0xFF, 0x2F, 0x00, //
+                0x00, 0x90, 0x3C, 0x64, // after End of Track
+            ]],
+        );
+
+        let smf = Smf::from_bytes(&bytes, "test").unwrap();
+        assert_eq!(smf.format, 0);
+        assert_eq!(smf.division, Division::Metrical(96));
+        assert_eq!(smf.tracks.len(), 1);
+        assert_eq!(
+            smf.tracks[0][0],
+            TrackEvent {
+                tick: 0,
+                event: SmfEvent::Meta {
+                    kind: META_TRACK_NAME,
+                    data: b"song".to_vec()
+                }
+            }
+        );
+        assert_eq!(
+            smf.tracks[0][5],
+            TrackEvent {
+                tick: 96,
+                event: SmfEvent::Tempo(1_000_000)
+            }
+        );
+        assert!(smf.tracks[0].iter().any(|event| event.event
+            == SmfEvent::Meta {
+                kind: 0x45,
+                data: vec![]
+            }));
+
+        assert_eq!(
+            smf.timed_messages(),
+            vec![
+                (0, vec![0xF0, 0x7E, 0x7F, 0x09, 0x01, 0xF7]),
+                (0, vec![0x90, 0x3C, 0x64]),
+                (0, vec![0x90, 0x40, 0x64]),
+                (500_000, vec![0x90, 0x3C, 0x00]),
+                (1_000_000, vec![0xC1, 0x05]),
+                (3_000_000, vec![0x80, 0x40, 0x00]),
+                (3_000_000, vec![0xF8]),
+            ]
+        );
+        assert_eq!(smf.channels(), vec![0, 1]);
+    }
+
+    #[test]
+    fn test_parse_format_1_with_tempo_map() {
+        let tempo_track: &[u8] = &[
+            0x00, 0xFF, 0x51, 0x03, 0x07, 0xA1, 0x20, // 120 BPM
+            0x83, 0x60, 0xFF, 0x51, 0x03, 0x03, 0xD0, 0x90, // 240 BPM after 480 ticks
+            0x00, 0xFF, 0x2F, 0x00,
+        ];
+        let notes: &[u8] = &[
+            0x00, 0x9F, 0x3C, 0x64, //
+            0x83, 0x60, 0x9F, 0x3E, 0x64, //
+            0x83, 0x60, 0x8F, 0x3E, 0x00, // no End of Track
+        ];
+        // An unknown chunk between the tracks is skipped
+        let mut bytes = file(1, 480, &[tempo_track]);
+        bytes.extend_from_slice(b"XFIH\0\0\0\x02ab");
+        bytes.extend_from_slice(&file(1, 480, &[notes])[14..]);
+        bytes[11] = 2;
+
+        let smf = Smf::from_bytes(&bytes, "test").unwrap();
+        assert_eq!(smf.tracks.len(), 2);
+        assert_eq!(
+            smf.timed_messages(),
+            vec![
+                (0, vec![0x9F, 0x3C, 0x64]),
+                (500_000, vec![0x9F, 0x3E, 0x64]),
+                (750_000, vec![0x8F, 0x3E, 0x00]),
+            ]
+        );
+        assert_eq!(smf.channels(), vec![15]);
+    }
+
+    #[test]
+    fn test_parse_timecode_division() {
+        // 25 frames per second, 40 ticks per frame: one tick per millisecond
+        let bytes = file(
+            0,
+            0xE728,
+            &[&[0x00, 0x90, 0x3C, 0x64, 0x87, 0x68, 0x80, 0x3C, 0x00]],
+        );
+        let smf = Smf::from_bytes(&bytes, "test").unwrap();
+
+        assert_eq!(
+            smf.division,
+            Division::Timecode {
+                fps: 25,
+                ticks_per_frame: 40
+            }
+        );
+        assert_eq!(smf.timed_messages()[1], (1_000_000, vec![0x80, 0x3C, 0x00]));
+    }
+
+    #[test]
+    fn test_writer_output_parses_back() {
+        let mut writer = SmfWriter::new(DEFAULT_PPQ, DEFAULT_TEMPO)
+            .unwrap()
+            .track_name("pf");
+        writer.push(0, &[0xB0, 0x7A, 0x00]);
+        writer.push(250_000, &[0xF0, 0x43, 0x10, 0xF7]);
+        writer.push(1_000_000, &[0xB0, 0x7A, 0x7F]);
+
+        let smf = Smf::from_bytes(&writer.to_bytes(), "test").unwrap();
+        assert_eq!(smf.timed_messages(), writer.events);
+    }
+
+    #[test]
+    fn test_reject_invalid_files() {
+        let cases: [(Vec<u8>, &str); 8] = [
+            (
+                b"RIFF\0\0\0\x06".to_vec(),
+                "at byte 0: not a Standard MIDI File",
+            ),
+            (
+                file(0, 96, &[])[..10].to_vec(),
+                "at byte 10: unexpected end of data",
+            ),
+            (file(2, 96, &[&[]]), "format 2 is not supported"),
+            (
+                file(0, 96, &[&[], &[]]),
+                "format 0 must have exactly one track",
+            ),
+            (
+                file(0, 96, &[&[0x00, 0x3C, 0x64]]),
+                "at byte 23: data byte 3C without a status byte",
+            ),
+            (
+                file(0, 96, &[&[0x00, 0x90, 0x3C, 0xF8]]),
+                "at byte 23: status byte F8 inside a 90 message",
+            ),
+            (
+                file(0, 96, &[&[0x00, 0xF2, 0x00, 0x00]]),
+                "status byte F2 is not allowed in a track",
+            ),
+            (
+                file(0, 96, &[&[0x00, 0xFF, 0x51, 0x02, 0x01, 0x02]]),
+                "Set Tempo with 2 data bytes",
+            ),
+        ];
+
+        for (bytes, expected) in cases {
+            let error = Smf::from_bytes(&bytes, "'song.mid'").unwrap_err();
+            assert!(
+                error.to_string().contains(expected),
+                "{:?} does not mention {:?}",
+                error.to_string(),
+                expected
+            );
+        }
+
+        let mut truncated = file(0, 96, &[&[0x00, 0x90, 0x3C, 0x64]]);
+        truncated.truncate(truncated.len() - 1);
+        assert_eq!(
+            Smf::from_bytes(&truncated, "'song.mid'")
+                .unwrap_err()
+                .to_string(),
+            "Invalid MIDI file 'song.mid' at byte 14: chunk of 4 bytes runs past the end of the file"
+        );
+    }
+
+    #[test]
+    fn test_play_messages() {
+        let messages = vec![(0, vec![0x90, 0x3C, 0x64]), (1_000, vec![0x80, 0x3C, 0x00])];
+        let mut sink = RecordingSink::new();
+
+        let played = play_messages(&mut sink, &messages, &AtomicBool::new(false)).unwrap();
+        assert_eq!(played, 2);
+        assert_eq!(
+            sink.messages,
+            vec![vec![0x90, 0x3C, 0x64], vec![0x80, 0x3C, 0x00]]
+        );
+
+        let mut sink = RecordingSink::new();
+        let played = play_messages(&mut sink, &messages, &AtomicBool::new(true)).unwrap();
+        assert_eq!(played, 0);
+        assert!(sink.messages.is_empty());
+    }
+
+    #[test]
+    fn test_record_messages() {
+        let (sender, receiver) = mpsc::channel();
+        sender.send((5_000, vec![0xFE])).unwrap();
+        sender.send((10_000, vec![0x90, 0x3C, 0x64])).unwrap();
+        sender.send((12_000, vec![0xF8])).unwrap();
+        sender.send((510_000, vec![0x80, 0x3C, 0x00])).unwrap();
+        drop(sender);
+
+        let mut smf = SmfWriter::new(DEFAULT_PPQ, DEFAULT_TEMPO).unwrap();
+        let recorded = record_messages(&receiver, &mut smf, None, &AtomicBool::new(false));
+        assert_eq!(recorded, 2);
+        assert_eq!(
+            smf.events,
+            vec![
+                (0, vec![0x90, 0x3C, 0x64]),
+                (500_000, vec![0x80, 0x3C, 0x00])
+            ]
+        );
+    }
+
+    #[test]
+    fn test_record_keeps_leading_silence() {
+        let (sender, receiver) = mpsc::channel();
+        sender.send((10_000, vec![0x90, 0x3C, 0x64])).unwrap();
+        sender.send((20_000, vec![0x80, 0x3C, 0x00])).unwrap();
+        drop(sender);
+
+        let start = Instant::now() - Duration::from_millis(250);
+        let mut smf = SmfWriter::new(DEFAULT_PPQ, DEFAULT_TEMPO).unwrap();
+        record_messages(&receiver, &mut smf, Some(start), &AtomicBool::new(false));
+
+        let (lead_in, _) = smf.events[0];
+        assert!(lead_in >= 250_000, "lead-in of {} µs", lead_in);
+        assert_eq!(smf.events[1].0, lead_in + 10_000);
+
+        let (_sender, receiver) = mpsc::channel();
+        let stopped = record_messages(&receiver, &mut smf, None, &AtomicBool::new(true));
+        assert_eq!(stopped, 0);
+    }
+}
@@ -0,0 +1,127 @@
+use crate::PianoffError;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// What the wizard last sent, offered as its defaults on the next run
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LastUsed {
+    /// Full name of the port, as listed by the backend
+    pub port: String,
+    pub value: u8,
+    /// Channel as sent, 0-15
+    pub channel: u8,
+}
+
+/// Small JSON file that remembers settings between runs
+/// Unknown fields are ignored, so older versions can read newer files
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct State {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_used: Option<LastUsed>,
+    /// Last Local Control value sent, by port name and then channel as sent
+    /// Devices cannot report this, so it is only as good as the last pianoff run
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub local_control: BTreeMap<String, BTreeMap<u8, u8>>,
+}
+
+impl State {
+    /// The Local Control value last sent to a port on a channel, if known
+    pub fn local_control(&self, port: &str, channel: u8) -> Option<u8> {
+        self.local_control.get(port)?.get(&channel).copied()
+    }
+
+    /// Records a Local Control value sent to a port on a channel
+    pub fn record_local_control(&mut self, port: &str, channel: u8, value: u8) {
+        self.local_control
+            .entry(port.to_string())
+            .or_default()
+            .insert(channel, value);
+    }
+
+    /// Reads the state file; a file that does not exist yet gives an empty state
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, PianoffError> {
+        let path = path.as_ref();
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(State::default()),
+            Err(e) => {
+                return Err(PianoffError::io(
+                    format!("Failed to read state '{}'", path.display()),
+                    e,
+                ));
+            }
+        };
+
+        serde_json::from_str(&text).map_err(|e| PianoffError::State {
+            path: path.display().to_string(),
+            reason: e.to_string(),
+        })
+    }
+
+    /// Writes the state file, creating its directory; the old file is replaced in one
+    /// step so an interrupted write cannot leave it half-written
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), PianoffError> {
+        let path = path.as_ref();
+        let context = || format!("Failed to write state '{}'", path.display());
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| PianoffError::io(context(), e))?;
+        }
+        let text = serde_json::to_string_pretty(self).map_err(|e| PianoffError::State {
+            path: path.display().to_string(),
+            reason: e.to_string(),
+        })?;
+
+        let temporary = path.with_extension("json.tmp");
+        fs::write(&temporary, text + "\n").map_err(|e| PianoffError::io(context(), e))?;
+        fs::rename(&temporary, path).map_err(|e| PianoffError::io(context(), e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::process;
+
+    #[test]
+    fn test_state_round_trip() {
+        let dir = env::temp_dir().join(format!("pianoff-state-{}", process::id()));
+        let path = dir.join("nested").join("state.json");
+
+        assert_eq!(State::load(&path).unwrap(), State::default());
+
+        let state = State {
+            last_used: Some(LastUsed {
+                port: "P-125:P-125 MIDI 1 20:0".to_string(),
+                value: 0,
+                channel: 3,
+            }),
+            ..State::default()
+        };
+        state.save(&path).unwrap();
+        assert_eq!(State::load(&path).unwrap(), state);
+
+        let mut state = State::load(&path).unwrap();
+        state.record_local_control("P-125", 0, 0);
+        state.record_local_control("P-125", 3, 127);
+        state.record_local_control("P-125", 0, 127);
+        state.save(&path).unwrap();
+        let state = State::load(&path).unwrap();
+        assert_eq!(state.local_control("P-125", 0), Some(127));
+        assert_eq!(state.local_control("P-125", 3), Some(127));
+        assert_eq!(state.local_control("P-125", 1), None);
+        assert_eq!(state.local_control("Other", 0), None);
+
+        fs::write(&path, "{ \"last_used\": 7 }").unwrap();
+        assert!(matches!(
+            State::load(&path),
+            Err(PianoffError::State { .. })
+        ));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,218 @@
+use crate::{MidiMessage, MidiMessageError, MidiSink, PianoffError, format_hex, parse_hex};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+
+/// A complete System Exclusive message: F0, 7-bit data bytes, F7
+/// Every constructor checks the framing, so a `SysEx` is always safe to send
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SysEx(Vec<u8>);
+
+impl SysEx {
+    /// Checks the bytes of exactly one message, F0 and F7 included
+    pub fn new(bytes: &[u8]) -> Result<Self, MidiMessageError> {
+        match bytes.first() {
+            None => Err(MidiMessageError::Empty),
+            Some(0xF0) => MidiMessage::from_bytes(bytes).map(|_| SysEx(bytes.to_vec())),
+            Some(&status) => Err(MidiMessageError::NotSysEx(status)),
+        }
+    }
+
+    /// Frames data bytes with F0 and F7
+    pub fn from_payload(payload: &[u8]) -> Result<Self, MidiMessageError> {
+        let message = MidiMessage::SysEx(payload.to_vec());
+        message.to_bytes().map(SysEx)
+    }
+
+    /// Parses exactly one message written as hex, in any form `parse_hex` accepts
+    pub fn from_hex(input: &str) -> Result<Self, PianoffError> {
+        Ok(SysEx::new(&parse_hex(input)?)?)
+    }
+
+    /// Splits bytes holding one or more messages back to back, as in a `.syx` file
+    /// `origin` names the bytes in errors, which give the offset of the bad message
+    pub fn split(bytes: &[u8], origin: &str) -> Result<Vec<Self>, PianoffError> {
+        let invalid = |offset: usize, reason: MidiMessageError| PianoffError::InvalidSysEx {
+            origin: origin.to_string(),
+            offset,
+            reason: reason.to_string(),
+        };
+
+        let mut messages = Vec::new();
+        let mut start = 0;
+        while start < bytes.len() {
+            let end = match bytes[start..].iter().position(|byte| *byte == 0xF7) {
+                Some(length) => start + length + 1,
+                None if bytes[start] == 0xF0 => {
+                    return Err(invalid(start, MidiMessageError::UnterminatedSysEx));
+                }
+                None => bytes.len(),
+            };
+            messages.push(SysEx::new(&bytes[start..end]).map_err(|e| invalid(start, e))?);
+            start = end;
+        }
+
+        if messages.is_empty() {
+            return Err(invalid(0, MidiMessageError::Empty));
+        }
+        Ok(messages)
+    }
+
+    /// Reads every message of a `.syx` file
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Vec<Self>, PianoffError> {
+        let path = path.as_ref();
+        let bytes = fs::read(path).map_err(|e| {
+            PianoffError::io(format!("Failed to read SysEx file '{}'", path.display()), e)
+        })?;
+        SysEx::split(&bytes, &format!("'{}'", path.display()))
+    }
+
+    /// The whole message, F0 and F7 included
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// The data bytes between F0 and F7
+    pub fn payload(&self) -> &[u8] {
+        &self.0[1..self.0.len() - 1]
+    }
+}
+
+impl fmt::Display for SysEx {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", format_hex(&self.0))
+    }
+}
+
+impl From<SysEx> for MidiMessage {
+    fn from(sysex: SysEx) -> Self {
+        MidiMessage::SysEx(sysex.payload().to_vec())
+    }
+}
+
+/// Sends SysEx messages in order, waiting `pacing` between them for devices with
+/// small input buffers; returns the confirmation text to display
+pub fn send_sysex<S: MidiSink + ?Sized>(
+    sink: &mut S,
+    messages: &[SysEx],
+    pacing: Duration,
+) -> Result<String, PianoffError> {
+    for (i, message) in messages.iter().enumerate() {
+        if i > 0 && !pacing.is_zero() {
+            thread::sleep(pacing);
+        }
+        sink.send(message.as_bytes())?;
+    }
+
+    match messages {
+        [message] => Ok(format!("✓ Successfully sent SysEx: {}", message)),
+        _ => Ok(format!(
+            "✓ Successfully sent {} SysEx messages ({} bytes)",
+            messages.len(),
+            messages
+                .iter()
+                .map(|message| message.as_bytes().len())
+                .sum::<usize>()
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RecordingSink;
+
+    #[test]
+    fn test_sysex_framing() {
+        let sysex = SysEx::new(&[0xF0, 0x43, 0x10, 0x4C, 0xF7]).unwrap();
+        assert_eq!(sysex.payload(), &[0x43, 0x10, 0x4C]);
+        assert_eq!(sysex.to_string(), "F0 43 10 4C F7");
+        assert_eq!(SysEx::from_payload(&[0x43, 0x10, 0x4C]).unwrap(), sysex);
+        assert_eq!(SysEx::from_hex("f0 43 10 4c f7").unwrap(), sysex);
+        assert_eq!(
+            MidiMessage::from(sysex),
+            MidiMessage::SysEx(vec![0x43, 0x10, 0x4C])
+        );
+
+        assert_eq!(SysEx::new(&[]), Err(MidiMessageError::Empty));
+        assert_eq!(
+            SysEx::new(&[0xB0, 0x7A, 0x00]),
+            Err(MidiMessageError::NotSysEx(0xB0))
+        );
+        assert_eq!(
+            SysEx::new(&[0xF0, 0x43, 0x10]),
+            Err(MidiMessageError::UnterminatedSysEx)
+        );
+        assert_eq!(
+            SysEx::new(&[0xF0, 0x43, 0x90, 0xF7]),
+            Err(MidiMessageError::InvalidDataByte {
+                position: 2,
+                byte: 0x90
+            })
+        );
+        assert!(matches!(
+            SysEx::new(&[0xF0, 0xF7, 0x00]),
+            Err(MidiMessageError::TrailingBytes { .. })
+        ));
+        assert!(SysEx::from_payload(&[0x43, 0xF7]).is_err());
+    }
+
+    #[test]
+    fn test_split_several_messages() {
+        let bytes = [
+            0xF0, 0x7E, 0x7F, 0x09, 0x01, 0xF7, //
+            0xF0, 0x41, 0x10, 0x42, 0x12, 0xF7, //
+            0xF0, 0xF7,
+        ];
+        let messages = SysEx::split(&bytes, "test").unwrap();
+        assert_eq!(messages.len(), 3);
+        assert_eq!(messages[1].as_bytes(), &bytes[6..12]);
+        assert_eq!(messages[2].payload(), &[] as &[u8]);
+
+        let cases: [(&[u8], &str); 4] = [
+            (&[], "at byte 0: Empty MIDI message"),
+            (
+                &[0xF0, 0x01, 0xF7, 0x01, 0xF7],
+                "at byte 3: Expected a SysEx message",
+            ),
+            (
+                &[0xF0, 0x01, 0xF7, 0xF0, 0x02],
+                "at byte 3: System Exclusive message is not terminated",
+            ),
+            (
+                &[0xF0, 0x01, 0xF7, 0xF0, 0xFE, 0xF7],
+                "at byte 3: Invalid data byte 0xFE at position 1",
+            ),
+        ];
+        for (bytes, expected) in cases {
+            let error = SysEx::split(bytes, "'patch.syx'").unwrap_err().to_string();
+            assert!(
+                error.starts_with("Invalid SysEx in 'patch.syx' ") && error.contains(expected),
+                "{:?} does not mention {:?}",
+                error,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_send_sysex() {
+        let messages = SysEx::split(&[0xF0, 0x01, 0xF7, 0xF0, 0x02, 0x03, 0xF7], "test").unwrap();
+        let mut sink = RecordingSink::new();
+
+        let confirmation = send_sysex(&mut sink, &messages, Duration::ZERO).unwrap();
+        assert_eq!(
+            confirmation,
+            "✓ Successfully sent 2 SysEx messages (7 bytes)"
+        );
+        assert_eq!(
+            sink.messages,
+            vec![vec![0xF0, 0x01, 0xF7], vec![0xF0, 0x02, 0x03, 0xF7]]
+        );
+
+        let confirmation = send_sysex(&mut sink, &messages[..1], Duration::ZERO).unwrap();
+        assert_eq!(confirmation, "✓ Successfully sent SysEx: F0 01 F7");
+    }
+}
@@ -0,0 +1,93 @@
+//! One-based channel numbering is process-wide, so it is tested in its own binary
+//! to keep it from leaking into tests that expect the default zero-based numbering.
+
+use midi_cc_sender::*;
+use std::time::Duration;
+
+#[test]
+fn test_one_based_numbering_end_to_end() {
+    set_channel_numbering(ChannelNumbering::OneBased);
+    assert_eq!(channel_numbering(), ChannelNumbering::OneBased);
+
+    // Input is read as 1-16 and converted to the wire channel
+    assert_eq!(validate_midi_channel("1"), (0, None));
+    assert_eq!(validate_midi_channel("16"), (15, None));
+
+    let (channel, warning) = validate_midi_channel("0");
+    assert_eq!(channel, 0);
+    assert_eq!(
+        warning.unwrap().to_string(),
+        "Warning: Channel 0 is out of range (1-16). Using default channel 1."
+    );
+
+    let (_, warning) = validate_midi_channel("x");
+    assert_eq!(
+        warning.unwrap().to_string(),
+        "Warning: Invalid channel 'x'. Using default channel 1."
+    );
+
+    let error = validate_midi_channel_with("17", 9, ValidationMode::Strict).unwrap_err();
+    assert_eq!(error.to_string(), "Invalid MIDI channel: 17. Must be 1-16.");
+
+    // The wire encoding is unchanged, only the displayed numbers move
+    let mut sink = RecordingSink::new();
+    let confirmation = send_midi_cc_122(&mut sink, 0, 15).unwrap();
+    assert_eq!(sink.messages, vec![vec![0xBF, 122, 0]]);
+    assert_eq!(
+        confirmation,
+        "✓ Successfully sent MIDI CC #122: Local Control Off (value: 0) on channel 16"
+    );
+
+    let confirmation = send_cc(&mut sink, 64, 127, 0).unwrap();
+    assert!(confirmation.ends_with("on channel 1"), "{}", confirmation);
+
+    let confirmation = send_channel_mode(&mut sink, ChannelMode::AllNotesOff, 9).unwrap();
+    assert!(confirmation.ends_with("on channel 10"), "{}", confirmation);
+
+    let confirmation = send_midi_cc_122_batch(&mut sink, 127, 0..=2, Duration::ZERO).unwrap();
+    assert!(
+        confirmation.ends_with("on channels 1, 2, 3"),
+        "{}",
+        confirmation
+    );
+
+    assert_eq!(
+        create_midi_cc_122_message(0, 16).unwrap_err().to_string(),
+        "Invalid MIDI channel: 17. Must be 1-16."
+    );
+
+    // Scripts say which numbering they use, so they mean the same with --one-based
+    let channels = |text: &str| -> Vec<Option<MidiMessage>> {
+        let script = Script::parse(text, "test").unwrap();
+        script
+            .steps
+            .iter()
+            .map(|line| line.step.message(9))
+            .collect()
+    };
+    assert_eq!(
+        channels("cc 122 0 ch=1\nprogram 5 ch=0"),
+        vec![
+            Some(MidiMessage::ControlChange {
+                channel: 1,
+                controller: 122,
+                value: 0
+            }),
+            Some(MidiMessage::ProgramChange {
+                channel: 0,
+                program: 5
+            }),
+        ]
+    );
+    assert_eq!(
+        channels("numbering one-based\ncc 122 0 ch=1"),
+        vec![Some(MidiMessage::ControlChange {
+            channel: 0,
+            controller: 122,
+            value: 0
+        })]
+    );
+
+    set_channel_numbering(ChannelNumbering::ZeroBased);
+    assert_eq!(validate_midi_channel("0"), (0, None));
+}
@@ -15,7 +15,9 @@ fn test_midi_value_validation_comprehensive_errors() {
         ("-128", 0, "Invalid value"),
         ("abc", 0, "Invalid value"),
         ("12.5", 0, "Invalid value"),
-        ("0x40", 0, "Invalid value"), // Hex
+        ("0x80", 0, "out of range"), // Hex above 0x7F
+        ("0xZZ", 0, "Invalid value"), // Malformed hex
+        ("101%", 0, "out of range"), // Percentage above 100
         ("64e0", 0, "Invalid value"), // Scientific notation
         ("!@#$", 0, "Invalid value"),
         ("", 0, ""), // Empty should not have warning
@@ -30,7 +32,15 @@ fn test_midi_value_validation_comprehensive_errors() {
             assert!(warning.is_none(), "Unexpected warning for input: '{}'", input);
         } else {
             assert!(warning.is_some(), "Expected warning for input: '{}'", input);
-            let warning_msg = warning.unwrap();
+            let warning = warning.unwrap();
+            if expected_keyword == "out of range" {
+                assert!(matches!(warning, ValidationWarning::OutOfRange { .. }),
+                       "Expected out-of-range warning for input: '{}'", input);
+            } else {
+                assert!(matches!(warning, ValidationWarning::Invalid { .. }),
+                       "Expected invalid-input warning for input: '{}'", input);
+            }
+            let warning_msg = warning.to_string();
             assert!(warning_msg.contains(expected_keyword), 
                    "Warning '{}' doesn't contain '{}' for input: '{}'", 
                    warning_msg, expected_keyword, input);
@@ -68,7 +78,15 @@ fn test_midi_channel_validation_comprehensive_errors() {
             assert!(warning.is_none(), "Unexpected warning for input: '{}'", input);
         } else {
             assert!(warning.is_some(), "Expected warning for input: '{}'", input);
-            let warning_msg = warning.unwrap();
+            let warning = warning.unwrap();
+            if expected_keyword == "out of range" {
+                assert!(matches!(warning, ValidationWarning::OutOfRange { .. }),
+                       "Expected out-of-range warning for input: '{}'", input);
+            } else {
+                assert!(matches!(warning, ValidationWarning::Invalid { .. }),
+                       "Expected invalid-input warning for input: '{}'", input);
+            }
+            let warning_msg = warning.to_string();
             assert!(warning_msg.contains(expected_keyword), 
                    "Warning '{}' doesn't contain '{}' for input: '{}'", 
                    warning_msg, expected_keyword, input);
@@ -126,7 +144,7 @@ fn test_validation_warning_message_content() {
     
     // Test value warning messages
     let (_, warning) = validate_midi_value("128");
-    let warning_msg = warning.unwrap();
+    let warning_msg = warning.unwrap().to_string();
     assert!(warning_msg.contains("Warning"));
     assert!(warning_msg.contains("128"));
     assert!(warning_msg.contains("out of range"));
@@ -134,7 +152,7 @@ fn test_validation_warning_message_content() {
     assert!(warning_msg.contains("default value 0"));
     
     let (_, warning) = validate_midi_value("abc");
-    let warning_msg = warning.unwrap();
+    let warning_msg = warning.unwrap().to_string();
     assert!(warning_msg.contains("Warning"));
     assert!(warning_msg.contains("Invalid value"));
     assert!(warning_msg.contains("abc"));
@@ -142,7 +160,7 @@ fn test_validation_warning_message_content() {
     
     // Test channel warning messages
     let (_, warning) = validate_midi_channel("16");
-    let warning_msg = warning.unwrap();
+    let warning_msg = warning.unwrap().to_string();
     assert!(warning_msg.contains("Warning"));
     assert!(warning_msg.contains("16"));
     assert!(warning_msg.contains("out of range"));
@@ -150,7 +168,7 @@ fn test_validation_warning_message_content() {
     assert!(warning_msg.contains("default channel 0"));
     
     let (_, warning) = validate_midi_channel("xyz");
-    let warning_msg = warning.unwrap();
+    let warning_msg = warning.unwrap().to_string();
     assert!(warning_msg.contains("Warning"));
     assert!(warning_msg.contains("Invalid channel"));
     assert!(warning_msg.contains("xyz"));
@@ -239,11 +257,11 @@ fn test_memory_safety_with_large_inputs() {
     let (value, warning) = validate_midi_value(&large_string);
     assert_eq!(value, 0);
     assert!(warning.is_some());
-    assert!(warning.unwrap().contains("Invalid value"));
+    assert!(warning.unwrap().to_string().contains("Invalid value"));
     
     let large_text = "a".repeat(1000); // Very large text string
     let (value, warning) = validate_midi_value(&large_text);
     assert_eq!(value, 0);
     assert!(warning.is_some());
-    assert!(warning.unwrap().contains("Invalid value"));
+    assert!(warning.unwrap().to_string().contains("Invalid value"));
 }
\ No newline at end of file
@@ -121,7 +121,7 @@ fn test_comprehensive_error_scenarios() {
     let special_chars = vec![
         "!@#$%",
         "64.0",
-        "0x40", // Hex notation
+        "0x", // Hex prefix without digits
         "64e0", // Scientific notation
     ];
 
@@ -159,4 +159,28 @@ fn test_midi_protocol_compliance() {
             assert!(message[2] < 0x80, "Data byte 2 should not have MSB set");
         }
     }
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_extended_value_forms() {
+    // Keywords, hex and percentages are accepted wherever values are validated
+    let cases = vec![
+        ("on", 127),
+        ("OFF", 0),
+        ("true", 127),
+        ("false", 0),
+        ("Max", 127),
+        ("0x7F", 127),
+        ("0x40", 64),
+        ("50%", 64),
+        ("100%", 127),
+        ("0%", 0),
+    ];
+
+    for (input, expected) in cases {
+        let (value, warning) = validate_midi_value(input);
+        assert_eq!(value, expected, "Failed for input: '{}'", input);
+        assert!(warning.is_none(), "Unexpected warning for input: '{}'", input);
+        assert_eq!(create_midi_cc_122_message(value, 0).unwrap()[2], expected);
+    }
+}
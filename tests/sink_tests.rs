@@ -0,0 +1,63 @@
+use midi_cc_sender::*;
+use mockall::mock;
+use mockall::predicate::eq;
+
+mock! {
+    Sink {}
+    impl MidiSink for Sink {
+        fn send(&mut self, message: &[u8]) -> Result<(), PianoffError>;
+    }
+}
+
+#[test]
+fn test_send_path_delivers_exact_bytes() {
+    // The sink should receive exactly one well-formed CC #122 message
+    let mut sink = MockSink::new();
+    sink.expect_send()
+        .with(eq(&[0xB5, 122, 127][..]))
+        .times(1)
+        .returning(|_| Ok(()));
+
+    let confirmation = send_midi_cc_122(&mut sink, 127, 5).unwrap();
+    assert!(confirmation.contains("Local Control On"));
+    assert!(confirmation.contains("channel 5"));
+}
+
+#[test]
+fn test_send_path_wraps_transport_errors() {
+    // Transport failures should surface with context and no confirmation
+    let mut sink = MockSink::new();
+    sink.expect_send()
+        .times(1)
+        .returning(|_| Err(PianoffError::Send("device unplugged".to_string())));
+
+    let error = send_midi_cc_122(&mut sink, 0, 0).unwrap_err();
+    assert!(matches!(error, PianoffError::Send(_)));
+    let error = error.to_string();
+    assert!(error.contains("Failed to send MIDI message"));
+    assert!(error.contains("device unplugged"));
+}
+
+#[test]
+fn test_send_path_never_reaches_sink_on_invalid_input() {
+    // Validation errors must be raised before anything is sent
+    let mut sink = MockSink::new();
+    sink.expect_send().times(0);
+
+    assert!(send_midi_cc_122(&mut sink, 200, 0).is_err());
+    assert!(send_midi_cc_122(&mut sink, 0, 20).is_err());
+}
+
+#[test]
+fn test_send_path_through_boxed_sink() {
+    // Sinks can be chosen at runtime behind a trait object
+    let mut sink: Box<dyn MidiSink> = Box::new(HexDumpSink::new(Vec::new()));
+    send_midi_cc_122(&mut sink, 64, 1).unwrap();
+
+    let mut recorder = RecordingSink::new();
+    {
+        let mut boxed: Box<dyn MidiSink + '_> = Box::new(&mut recorder);
+        send_midi_cc_122(&mut boxed, 64, 1).unwrap();
+    }
+    assert_eq!(recorder.messages, vec![vec![0xB1, 122, 64]]);
+}